@@ -0,0 +1,224 @@
+use bevy::prelude::*;
+
+/// Particles per leaf before a node is split.
+const LEAF_SIZE: usize = 8;
+/// Subtrees with fewer particles than this are built on the current thread.
+const PARALLEL_THRESHOLD: usize = 1024;
+/// Stops splitting coincident particles.
+const MAX_DEPTH: u32 = 32;
+
+/// Barnes–Hut quadtree over weighted points, such as charges or masses.
+/// Distant nodes act as two points, their positive and their negative weights
+/// each at its own centre, so that a node of mixed signs keeps its dipole.
+pub struct QuadTree<'a> {
+    positions: &'a [Vec2],
+    weights: &'a [f32],
+    root: Option<Node>,
+}
+
+struct Node {
+    /// Positive then negative weights, each at its weighted centre
+    poles: [Pole; 2],
+    /// Side length of the node's square
+    size: f32,
+    kind: NodeKind,
+}
+
+#[derive(Clone, Copy, Default)]
+struct Pole {
+    centre: Vec2,
+    weight: f32,
+}
+
+enum NodeKind {
+    Leaf(Vec<usize>),
+    Branch(Vec<Node>),
+}
+
+impl<'a> QuadTree<'a> {
+    pub fn new(positions: &'a [Vec2], weights: &'a [f32]) -> Self {
+        let indices: Vec<usize> = (0..positions.len()).filter(|i| weights[*i] != 0.0).collect();
+        if indices.is_empty() {
+            return QuadTree { positions, weights, root: None };
+        }
+
+        let (min, max) = indices.iter().fold((Vec2::MAX, Vec2::MIN), |(min, max), i| {
+            (min.min(positions[*i]), max.max(positions[*i]))
+        });
+        let size = (max - min).max_element().max(f32::EPSILON);
+        let root = build(positions, weights, indices, (min + max) / 2.0, size, 0);
+
+        QuadTree { positions, weights, root: Some(root) }
+    }
+
+    /// sum_j w_j f(r) (point - x_j) / r over every point except `skip`, with
+    /// nodes narrower than `theta` times their distance taken as one point.
+    pub fn accumulate(&self, point: Vec2, skip: usize, theta: f32, f: impl Fn(f32) -> f32) -> Vec2 {
+        let mut total = Vec2::ZERO;
+        if let Some(root) = &self.root {
+            self.visit(root, point, skip, theta, &f, &mut total);
+        }
+        total
+    }
+
+    fn visit(&self, node: &Node, point: Vec2, skip: usize, theta: f32, f: &impl Fn(f32) -> f32, total: &mut Vec2) {
+        match &node.kind {
+            NodeKind::Leaf(indices) => {
+                for &j in indices {
+                    if j != skip {
+                        *total += self.weights[j] * pair(point, self.positions[j], f);
+                    }
+                }
+            }
+            NodeKind::Branch(children) => {
+                let far = node.poles
+                    .iter()
+                    .all(|pole| pole.weight == 0.0 || node.size < theta * point.distance(pole.centre));
+                if far {
+                    for pole in node.poles {
+                        *total += pole.weight * pair(point, pole.centre, f);
+                    }
+                } else {
+                    for child in children {
+                        self.visit(child, point, skip, theta, f, total);
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn pair(point: Vec2, source: Vec2, f: &impl Fn(f32) -> f32) -> Vec2 {
+    let offset = point - source;
+    let distance = offset.length();
+    if distance <= f32::EPSILON {
+        return Vec2::ZERO;
+    }
+    f(distance) * offset / distance
+}
+
+fn build(positions: &[Vec2], weights: &[f32], indices: Vec<usize>, middle: Vec2, size: f32, depth: u32) -> Node {
+    if indices.len() <= LEAF_SIZE || depth >= MAX_DEPTH {
+        let poles = aggregate(indices.iter().map(|i| (positions[*i], weights[*i])));
+        return Node { poles, size, kind: NodeKind::Leaf(indices) };
+    }
+
+    let mut quadrants: [Vec<usize>; 4] = Default::default();
+    for i in indices.iter().copied() {
+        let position = positions[i];
+        let quadrant = (position.x >= middle.x) as usize + 2 * (position.y >= middle.y) as usize;
+        quadrants[quadrant].push(i);
+    }
+
+    let parallel = indices.len() >= PARALLEL_THRESHOLD;
+    let child = |quadrant: usize, indices: Vec<usize>| {
+        if indices.is_empty() {
+            return None;
+        }
+        let offset = Vec2::new(
+            if quadrant % 2 == 1 { 0.25 } else { -0.25 },
+            if quadrant / 2 == 1 { 0.25 } else { -0.25 },
+        ) * size;
+        Some(build(positions, weights, indices, middle + offset, size / 2.0, depth + 1))
+    };
+
+    let [a, b, c, d] = quadrants;
+    let (ab, cd) = if parallel {
+        rayon::join(|| rayon::join(|| child(0, a), || child(1, b)),
+                    || rayon::join(|| child(2, c), || child(3, d)))
+    } else {
+        ((child(0, a), child(1, b)), (child(2, c), child(3, d)))
+    };
+    let children: Vec<Node> = [ab.0, ab.1, cd.0, cd.1].into_iter().flatten().collect();
+
+    let poles = aggregate(children.iter().flat_map(|child| child.poles.map(|pole| (pole.centre, pole.weight))));
+    Node { poles, size, kind: NodeKind::Branch(children) }
+}
+
+// Total positive and negative weight, each at its weighted centre
+fn aggregate(points: impl Iterator<Item = (Vec2, f32)>) -> [Pole; 2] {
+    let mut poles = [Pole::default(); 2];
+    for (position, w) in points {
+        let pole = &mut poles[(w < 0.0) as usize];
+        pole.centre += w * position;
+        pole.weight += w;
+    }
+    for pole in poles.iter_mut() {
+        if pole.weight != 0.0 {
+            pole.centre /= pole.weight;
+        }
+    }
+    poles
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    use super::*;
+
+    fn law(r: f32) -> f32 {
+        r / (r * r + 1.0).powf(1.5)
+    }
+
+    fn direct(positions: &[Vec2], weights: &[f32], i: usize) -> Vec2 {
+        (0..positions.len())
+            .filter(|j| *j != i)
+            .map(|j| weights[j] * pair(positions[i], positions[j], &law))
+            .sum()
+    }
+
+    fn sample(n: usize) -> (Vec<Vec2>, Vec<f32>) {
+        let mut rng = StdRng::seed_from_u64(7);
+        let positions = (0..n)
+            .map(|_| Vec2::new(rng.gen_range(-500.0..500.0), rng.gen_range(-500.0..500.0)))
+            .collect();
+        let weights = (0..n).map(|_| rng.gen_range(0.5..1.5)).collect();
+        (positions, weights)
+    }
+
+    fn relative_error(positions: &[Vec2], weights: &[f32], theta: f32) -> f32 {
+        let tree = QuadTree::new(positions, weights);
+        let (mut error, mut magnitude) = (0.0, 0.0);
+        for i in 0..positions.len() {
+            let exact = direct(positions, weights, i);
+            error += tree.accumulate(positions[i], i, theta, law).distance(exact);
+            magnitude += exact.length();
+        }
+        error / magnitude
+    }
+
+    #[test]
+    fn zero_opening_angle_is_exact() {
+        let (positions, weights) = sample(300);
+        let tree = QuadTree::new(&positions, &weights);
+
+        for i in 0..positions.len() {
+            let exact = direct(&positions, &weights, i);
+            let approximate = tree.accumulate(positions[i], i, 0.0, law);
+            assert!(approximate.distance(exact) <= 1e-4 * exact.length().max(1e-6));
+        }
+    }
+
+    #[test]
+    fn opening_angle_bounds_the_error() {
+        let (positions, weights) = sample(2000);
+        let error = relative_error(&positions, &weights, 0.5);
+        assert!(error < 1e-2, "relative error {error}");
+    }
+
+    // Polarised neutral pairs leave every node with no net weight, so the
+    // whole force comes from the dipoles
+    #[test]
+    fn mixed_signs_keep_the_error_bounded() {
+        let (centres, magnitudes) = sample(1000);
+        let (mut positions, mut weights) = (Vec::new(), Vec::new());
+        for (centre, magnitude) in centres.into_iter().zip(magnitudes) {
+            positions.extend([centre, centre + Vec2::new(5.0, 0.0)]);
+            weights.extend([magnitude, -magnitude]);
+        }
+        let error = relative_error(&positions, &weights, 0.5);
+        assert!(error < 1e-2, "relative error {error}");
+    }
+}
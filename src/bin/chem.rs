@@ -0,0 +1,145 @@
+use std::error::Error;
+
+use bevy::log::LogPlugin;
+use bevy::prelude::*;
+
+use chem::config::SimConfig;
+use chem::headless::Headless;
+use chem::snapshot::Snapshot;
+use chem::solute::SoluteSettings;
+use chem::FluidSimPlugin;
+
+const USAGE: &str = "usage: chem [scene.json] [--snapshot <file>] \
+                     [--headless [--frames <n>] [--dt <seconds>] [--output <file>]]";
+
+/// Command line arguments, see `USAGE`.
+#[derive(Clone, Debug)]
+struct Args {
+    scene: Option<String>,
+    /// Start from a saved snapshot instead of the scene
+    snapshot: Option<String>,
+    /// Run without a window for `frames` fixed steps of `dt`, then write the
+    /// final state to `output`
+    headless: bool,
+    frames: u32,
+    dt: f32,
+    output: String,
+}
+
+impl Default for Args {
+    fn default() -> Self {
+        Args {
+            scene: None,
+            snapshot: None,
+            headless: false,
+            frames: 600,
+            dt: 1.0 / 60.0,
+            output: "results.json".into(),
+        }
+    }
+}
+
+impl Args {
+    fn parse() -> Self {
+        let mut args = Args::default();
+        let mut arguments = std::env::args().skip(1);
+        while let Some(argument) = arguments.next() {
+            match argument.as_str() {
+                "--snapshot" => args.snapshot = Some(value(&mut arguments, "--snapshot")),
+                "--headless" => args.headless = true,
+                "--frames" => args.frames = number(&mut arguments, "--frames"),
+                "--dt" => args.dt = number(&mut arguments, "--dt"),
+                "--output" => args.output = value(&mut arguments, "--output"),
+                flag if flag.starts_with("--") => usage(&format!("unknown flag {flag}")),
+                _ if args.scene.is_none() => args.scene = Some(argument),
+                _ => usage(&format!("unexpected argument {argument}")),
+            }
+        }
+        if !args.dt.is_finite() || args.dt <= 0.0 {
+            usage(&format!("--dt must be positive, not {}", args.dt));
+        }
+        if args.frames == 0 {
+            usage("--frames must be at least 1");
+        }
+        args
+    }
+}
+
+fn value(arguments: &mut impl Iterator<Item = String>, flag: &str) -> String {
+    arguments.next().unwrap_or_else(|| usage(&format!("{flag} needs a value")))
+}
+
+fn number<T: std::str::FromStr>(arguments: &mut impl Iterator<Item = String>, flag: &str) -> T {
+    let argument = value(arguments, flag);
+    argument.parse().unwrap_or_else(|_| usage(&format!("{flag} expects a number, not {argument}")))
+}
+
+fn usage(message: &str) -> ! {
+    eprintln!("{message}\n{USAGE}");
+    std::process::exit(2);
+}
+
+/// Unwraps the result of reading `path`, exiting with the error otherwise.
+fn or_exit<T>(result: Result<T, Box<dyn Error>>, path: &str) -> T {
+    result.unwrap_or_else(|error| {
+        eprintln!("Could not load {path}: {error}");
+        std::process::exit(1);
+    })
+}
+
+/// The scene at `path`, or the default scene. Exits if it cannot be read.
+fn load_scene(path: Option<&str>) -> SimConfig {
+    path.map_or_else(SimConfig::default, |path| or_exit(SimConfig::load(path), path))
+}
+
+fn main() {
+    let args = Args::parse();
+    let snapshot = args.snapshot.as_deref().map(|path| or_exit(Snapshot::load(path), path));
+    let config = match &snapshot {
+        Some(snapshot) => snapshot.config.clone(),
+        None => load_scene(args.scene.as_deref()),
+    };
+    let plugin = match snapshot {
+        Some(snapshot) => FluidSimPlugin::new().with_snapshot(snapshot),
+        None => FluidSimPlugin::new().with_config(config.clone()),
+    };
+
+    let mut app = App::new();
+    if args.headless {
+        app.add_plugins((MinimalPlugins, LogPlugin::default(), TransformPlugin, HierarchyPlugin))
+            .add_plugins(plugin.headless(args.frames, args.dt, args.output))
+            .add_systems(Last, fail_headless_run);
+    } else {
+        app.insert_resource(Msaa::Off)
+            .add_plugins((
+                DefaultPlugins.set(WindowPlugin {
+                    primary_window: Some(Window {
+                        title: "I am a window!".into(),
+                        resolution: (config.window_width, config.window_height).into(),
+                        ..default()
+                    }),
+                    ..default()
+                }),
+            ))
+            .add_plugins(plugin)
+            .add_systems(Startup, setup_graphics);
+    }
+
+    app.insert_resource(SoluteSettings {
+            display: Some(0),
+            ..default()
+        })
+        .run();
+}
+
+// Fail the batch job rather than exit cleanly without results
+fn fail_headless_run(headless: Res<Headless>) {
+    if headless.error.is_some() {
+        std::process::exit(1);
+    }
+}
+
+fn setup_graphics(mut commands: Commands) 
+{
+    commands.spawn(Camera2dBundle::default());
+}
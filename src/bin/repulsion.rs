@@ -0,0 +1,52 @@
+use bevy::prelude::*;
+
+use chem::config::SimConfig;
+use chem::electrostatics::{CoulombMethod, ElectrostaticSettings};
+use chem::pressure::EquationOfState;
+use chem::species::Species;
+use chem::FluidSimPlugin;
+
+// Goal 1: Get two particles to repel from each other. Like charges and no
+// other forces, so the block spreads out until the walls hold it.
+fn main() {
+    let config = SimConfig {
+        n_particles: 160,
+        particle_spacing: 4.0,
+        initial_velocity: [0.0, 0.0],
+        charge: 1.0,
+        gravity: [0.0, 0.0],
+        equation_of_state: EquationOfState::IdealGas {
+            stiffness: 0.0,
+            rest_density: 3e-4,
+        },
+        viscosity: 0.0,
+        floor_temperature: None,
+        species: vec![Species { surface_tension: 0.0, ..Species::water() }],
+        ..default()
+    };
+
+    App::new()
+        .insert_resource(Msaa::Off)
+        .add_plugins((
+            DefaultPlugins.set(WindowPlugin {
+                primary_window: Some(Window {
+                    title: "I am a window!".into(),
+                    resolution: (config.window_width, config.window_height).into(),
+                    ..default()
+                }),
+                ..default()
+            }),
+        ))
+        .add_plugins(FluidSimPlugin::new().with_config(config))
+        .insert_resource(ElectrostaticSettings {
+            method: CoulombMethod::Direct,
+            ..default()
+        })
+        .add_systems(Startup, setup_graphics)
+        .run();
+}
+
+fn setup_graphics(mut commands: Commands) 
+{
+    commands.spawn(Camera2dBundle::default());
+}
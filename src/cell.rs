@@ -0,0 +1,154 @@
+use bevy::prelude::*;
+use bevy_prototype_lyon::prelude::*;
+use bevy_rapier2d::prelude::*;
+
+use crate::config::SimConfig;
+use crate::heat::HeatSettings;
+use crate::kernel::Kernels;
+use crate::pressure::EquationOfState;
+use crate::solute::{Concentrations, SoluteSettings};
+use crate::spatial_hash::SpatialHash;
+use crate::species::Species;
+use crate::{Density, Particle};
+
+/// A square of the background grid, sampling the fluid for display.
+#[derive(Component)]
+pub struct Cell {
+    pub density: f32,
+    pub pressure: f32,
+    pub temperature: f32,
+    /// Concentration of the displayed solute, if any
+    pub concentration: Option<f32>,
+}
+
+impl Cell {
+    pub fn update_density(&mut self, density: f32) {
+        self.density += density;
+    }
+
+    pub fn reset_density(&mut self) {
+        self.density = 0.0;
+    }
+
+    pub fn update_cell_colour(&self, fill: &mut Fill, rest_density: f32) {
+        let density = match self.concentration {
+            Some(concentration) => concentration.clamp(0.0, 1.0),
+            None => (self.density / (2.0 * rest_density)).clamp(0.0, 1.0),
+        };
+
+        let red = density; 
+        let blue = 1.0 - density; 
+        let green = 1.0 - (red - blue).abs();
+    
+        let colour = Color::rgb(red, green, blue);
+        fill.color = colour;
+    }
+
+    pub fn update_cell_temperature_colour(&self, fill: &mut Fill, settings: &HeatSettings) {
+        // Cells outside the fluid have no temperature
+        fill.color = if self.temperature > 0.0 {
+            settings.colour(self.temperature)
+        } else {
+            Color::BLACK
+        };
+    }
+}
+
+pub fn setup_cells(mut commands: Commands, config: Res<SimConfig>) {
+    let (width, height) = (config.window_width, config.window_height);
+    let cell_size = config.cell_size;
+    let cell_spacing = 0.0;
+
+    // Calculate the number of cells that can fit in the width and height
+    let cells_x = (width / (cell_size + cell_spacing)).floor() as i32;
+    let cells_y = (height / (cell_size + cell_spacing)).floor() as i32;
+
+    // Loop to create the grid of cells
+    for x in (-cells_x / 2.0 as i32)..(cells_x / 2.0 as i32) {
+        for y in (-cells_y / 2.0 as i32)..((cells_y / 2.0 as i32) + 1) {
+            // Calculate the position for each cell
+            let pos_x = x as f32 * cell_size + cell_size / 2.0;
+            let pos_y = y as f32 * cell_size;
+
+            // Create a cell and set its position
+            commands
+                .spawn(Collider::cuboid(cell_size / 2.0, cell_size / 2.0))
+                .insert(Sensor)
+                .insert(TransformBundle::from(
+                    Transform::from_xyz(pos_x, pos_y, -1.0)
+                ))
+                .insert(Cell {
+                    density: 0.0,
+                    pressure: 0.0,
+                    temperature: 0.0,
+                    concentration: None,
+                });
+        }
+    }
+}
+
+pub fn add_cell_shapes(mut commands: Commands,
+                       config: Res<SimConfig>,
+                       query: Query<(Entity, &Transform), Added<Cell>>) {
+    for (entity, transform) in query.iter() {
+        let shape = shapes::Rectangle {
+            extents: Vec2::new(config.cell_size, config.cell_size),
+            ..shapes::Rectangle::default()
+        };
+
+        commands.entity(entity).insert((
+            ShapeBundle {
+                path: GeometryBuilder::build_as(&shape),
+                spatial: SpatialBundle::from_transform(*transform),
+                ..default()
+            },
+            Fill::color(Color::BLUE),
+            Stroke::new(Color::BLACK, 1.0),
+        ));
+    }
+}
+
+// ρ = sum_j m_j W, and the displayed solute is Shepard interpolated, like the
+// cell temperature
+pub fn calculate_density(hash: Res<SpatialHash>,
+                         kernels: Res<Kernels>,
+                         eos: Res<EquationOfState>,
+                         solutes: Res<SoluteSettings>,
+                         particles: Query<(&Concentrations, &Density, &Species), With<Particle>>,
+                         mut cell_query: Query<(&Transform, &mut Cell, &mut Fill)>) {
+    let masses: Vec<f32> = hash.entities
+        .iter()
+        .map(|entity| particles.get(*entity).map_or(0.0, |(_, _, species)| species.mass))
+        .collect();
+    let samples: Option<Vec<(f32, f32)>> = solutes.display.map(|index| {
+        hash.entities
+            .iter()
+            .map(|entity| {
+                particles.get(*entity).map_or((0.0, 0.0), |(concentrations, density, species)| {
+                    let volume = if density.value > 0.0 { species.mass / density.value } else { 0.0 };
+                    (concentrations.0.get(index).copied().unwrap_or(0.0), volume)
+                })
+            })
+            .collect()
+    });
+
+    cell_query.par_iter_mut().for_each(|(transform, mut cell, mut fill)| {
+        let center = transform.translation.truncate();
+
+        cell.reset_density();
+        let (mut weighted, mut total) = (0.0, 0.0);
+        hash.for_each_neighbour(center, kernels.density.radius(), |i| {
+            let distance = center.distance(hash.positions[i]);
+            cell.update_density(masses[i] * kernels.density.value(distance));
+
+            if let Some(samples) = &samples {
+                let (concentration, volume) = samples[i];
+                let weight = volume * kernels.density.value(distance);
+                weighted += weight * concentration;
+                total += weight;
+            }
+        });
+        cell.concentration = samples.as_ref().map(|_| if total > 0.0 { weighted / total } else { 0.0 });
+        cell.update_cell_colour(&mut fill, eos.rest_density());
+    });
+}
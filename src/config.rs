@@ -0,0 +1,248 @@
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::electrostatics::ElectrostaticSettings;
+use crate::heat::{AmbientTemperature, HeatSettings, WallTemperature};
+use crate::integrator::{Bounds, Gravity, IntegratorMode};
+use crate::kernel::KernelTypes;
+use crate::pressure::{EquationOfState, PressureSolver};
+use crate::reaction::{Reaction, ReactionTable};
+use crate::rng::SimRng;
+use crate::spatial_hash::SpatialHash;
+use crate::species::{Species, SpeciesTable};
+use crate::viscosity::ViscositySettings;
+use crate::INFLUENCE_RADIUS;
+
+/// Scene and physics parameters, read from a JSON scene file. Fields missing from the file keep their defaults, so
+/// `{"n_particles": 400}` is a complete scene.
+#[derive(Resource, Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SimConfig {
+    pub window_width: f32,
+    pub window_height: f32,
+    pub cell_size: f32,
+    pub particle_radius: f32,
+    pub n_particles: usize,
+    /// Gap between neighbouring particles in the starting block
+    pub particle_spacing: f32,
+    /// Velocity every particle starts with
+    pub initial_velocity: [f32; 2],
+    /// Charge every particle starts with
+    pub charge: f32,
+    /// Smoothing length h shared by the kernels and the spatial hash
+    pub influence_radius: f32,
+    pub kernels: KernelTypes,
+    pub integrator: IntegratorMode,
+    pub pressure_solver: PressureSolver,
+    pub gravity: [f32; 2],
+    /// Written as e.g. `{"Tait": {"stiffness": 1000, "rest_density": 3e-4, "gamma": 7}}`
+    pub equation_of_state: EquationOfState,
+    /// μ, scaled per species by `Species::viscosity`
+    pub viscosity: f32,
+    pub coulomb_constant: f32,
+    pub ambient_temperature: f32,
+    /// Temperature the floor is held at, insulated if absent
+    pub floor_temperature: Option<f32>,
+    /// Seeds `SimRng`, which picks the starting species and decides reactions
+    pub seed: u64,
+    pub species: Vec<Species>,
+    pub reactions: Vec<Reaction>,
+}
+
+impl Default for SimConfig {
+    fn default() -> Self {
+        SimConfig {
+            window_width: 1320.0,
+            window_height: 780.0,
+            cell_size: 20.0,
+            particle_radius: 4.0,
+            n_particles: 100,
+            particle_spacing: 50.0,
+            initial_velocity: [100.0, 10.0],
+            charge: 0.0,
+            influence_radius: INFLUENCE_RADIUS,
+            kernels: KernelTypes::default(),
+            integrator: IntegratorMode::Rapier,
+            pressure_solver: PressureSolver::Wcsph,
+            gravity: [0.0, -50.0],
+            equation_of_state: EquationOfState::default(),
+            viscosity: 20.0,
+            coulomb_constant: 1e7,
+            ambient_temperature: 293.15,
+            floor_temperature: Some(373.15),
+            seed: 0,
+            species: vec![Species::water(), Species::oil()],
+            reactions: Vec::new(),
+        }
+    }
+}
+
+impl SimConfig {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, Box<dyn Error>> {
+        let contents = fs::read_to_string(path)?;
+        let config: SimConfig = serde_json::from_str(&contents)?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Rejects scenes that parse but cannot be simulated.
+    pub fn validate(&self) -> Result<(), String> {
+        let lengths = [
+            ("window_width", self.window_width),
+            ("window_height", self.window_height),
+            ("cell_size", self.cell_size),
+            ("particle_radius", self.particle_radius),
+            ("particle_spacing", self.particle_spacing),
+            ("influence_radius", self.influence_radius),
+        ];
+        for (name, value) in lengths {
+            if !value.is_finite() || value <= 0.0 {
+                return Err(format!("{name} must be positive, not {value}"));
+            }
+        }
+        if self.n_particles == 0 {
+            return Err("n_particles must be at least 1".into());
+        }
+        if self.species.is_empty() {
+            return Err("species must not be empty".into());
+        }
+        for (i, species) in self.species.iter().enumerate() {
+            let properties = [
+                ("mass", species.mass),
+                ("rest_density", species.rest_density),
+                ("heat_capacity", species.heat_capacity),
+            ];
+            for (name, value) in properties {
+                if !value.is_finite() || value <= 0.0 {
+                    return Err(format!("species {} {name} must be positive, not {value}", species.name));
+                }
+            }
+            if self.species[..i].iter().any(|other| other.name == species.name) {
+                return Err(format!("species {} is listed twice", species.name));
+            }
+        }
+
+        let species = SpeciesTable { species: self.species.clone() };
+        for reaction in self.reactions.iter() {
+            let names = reaction.reactants.iter().chain(reaction.products.iter());
+            for name in names {
+                if species.get(name).is_none() {
+                    return Err(format!("reaction {:?} -> {:?} uses unknown species {name}",
+                                       reaction.reactants, reaction.products));
+                }
+            }
+
+            if !reaction.conserves_mass(&species) {
+                let (before, after) = reaction.masses(&species);
+                return Err(format!("reaction {:?} -> {:?} does not conserve mass: {before} -> {after}",
+                                   reaction.reactants, reaction.products));
+            }
+        }
+        Ok(())
+    }
+
+    /// Inserts the resources derived from the scene, keeping any settings the
+    /// scene does not cover.
+    pub fn apply(&self, world: &mut World) {
+        let heat = world.get_resource::<HeatSettings>().copied().unwrap_or_default();
+        let viscosity = world.get_resource::<ViscositySettings>().copied().unwrap_or_default();
+        let electrostatics = world.get_resource::<ElectrostaticSettings>().copied().unwrap_or_default();
+
+        world.insert_resource(self.integrator);
+        world.insert_resource(self.pressure_solver);
+        world.insert_resource(Bounds::new(self.window_width, self.window_height, self.particle_radius));
+        world.insert_resource(Gravity(self.gravity()));
+        world.insert_resource(SpeciesTable { species: self.species.clone() });
+        world.insert_resource(ReactionTable { reactions: self.reactions.clone() });
+        world.insert_resource(SimRng::new(self.seed));
+        world.insert_resource(AmbientTemperature(self.ambient_temperature));
+        world.insert_resource(HeatSettings {
+            bottom: self.floor_temperature.map_or(WallTemperature::Insulated, WallTemperature::Fixed),
+            ..heat
+        });
+        world.insert_resource(self.kernels.build(self.influence_radius));
+        world.insert_resource(self.equation_of_state);
+        world.insert_resource(SpatialHash::new(self.influence_radius));
+        world.insert_resource(ViscositySettings { coefficient: self.viscosity, ..viscosity });
+        world.insert_resource(ElectrostaticSettings { coulomb_constant: self.coulomb_constant, ..electrostatics });
+        world.insert_resource(self.clone());
+    }
+
+    pub fn gravity(&self) -> Vec2 {
+        Vec2::from(self.gravity)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kernel::KernelType;
+
+    #[test]
+    fn missing_fields_keep_their_defaults() {
+        let config: SimConfig = serde_json::from_str(r#"{
+            "n_particles": 400,
+            "pressure_solver": "Iisph",
+            "species": [{"name": "brine", "rest_density": 3.3e-4, "colour": [0.2, 0.4, 1.0, 1.0]}]
+        }"#).unwrap();
+
+        assert_eq!(config.n_particles, 400);
+        assert_eq!(config.pressure_solver, PressureSolver::Iisph);
+        assert_eq!(config.window_width, SimConfig::default().window_width);
+
+        let brine = &config.species[0];
+        assert_eq!(brine.name, "brine");
+        assert_eq!(brine.rest_density, 3.3e-4);
+        assert_eq!(brine.mass, Species::water().mass);
+        assert_eq!(brine.colour, Color::rgba(0.2, 0.4, 1.0, 1.0));
+    }
+
+    #[test]
+    fn unusable_scenes_are_rejected() {
+        let parse = |json: &str| serde_json::from_str::<SimConfig>(json).unwrap().validate();
+
+        assert!(parse("{}").is_ok());
+        assert!(parse(r#"{"n_particles": 0}"#).is_err());
+        assert!(parse(r#"{"species": []}"#).is_err());
+        assert!(parse(r#"{"particle_radius": 0}"#).is_err());
+        assert!(parse(r#"{"particle_spacing": -4}"#).is_err());
+        assert!(parse(r#"{"window_height": 0}"#).is_err());
+        assert!(parse(r#"{"species": [{"name": "x", "mass": 0}]}"#).is_err());
+        assert!(parse(r#"{"species": [{"name": "x", "rest_density": 0}]}"#).is_err());
+        assert!(parse(r#"{"species": [{"name": "x", "heat_capacity": -1}]}"#).is_err());
+        assert!(parse(r#"{"species": [{"name": "x"}, {"name": "x", "mass": 2}]}"#).is_err());
+        assert!(parse(r#"{"reactions": [{"reactants": ["water", "salt"], "products": ["water", "water"],
+                                          "radius": 10, "rate": 1}]}"#).is_err());
+        assert!(parse(r#"{"reactions": [{"reactants": ["water", "water"], "products": ["oil", "oil"],
+                                          "radius": 10, "rate": 1}]}"#).is_err());
+        assert!(parse(r#"{"reactions": [{"reactants": ["water", "oil"], "products": ["oil", "water"],
+                                          "radius": 10, "rate": 1}]}"#).is_ok());
+    }
+
+    #[test]
+    fn default_scene_round_trips() {
+        let config = SimConfig::default();
+        let json = serde_json::to_string(&config).unwrap();
+        let loaded: SimConfig = serde_json::from_str(&json).unwrap();
+        assert_eq!(loaded.species, config.species);
+        assert_eq!(loaded.gravity, config.gravity);
+        assert_eq!(loaded.equation_of_state, config.equation_of_state);
+    }
+
+    #[test]
+    fn kernels_and_equation_of_state_come_from_the_scene() {
+        let config: SimConfig = serde_json::from_str(r#"{
+            "kernels": {"density": "Wendland"},
+            "equation_of_state": {"Tait": {"stiffness": 1000, "rest_density": 3e-4, "gamma": 7}}
+        }"#).unwrap();
+
+        assert_eq!(config.kernels.density, KernelType::Wendland);
+        assert_eq!(config.kernels.pressure, KernelTypes::default().pressure);
+        assert_eq!(config.equation_of_state,
+                   EquationOfState::Tait { stiffness: 1000.0, rest_density: 3e-4, gamma: 7.0 });
+    }
+}
@@ -0,0 +1,38 @@
+use bevy::prelude::*;
+use rayon::prelude::*;
+
+use crate::kernel::Kernels;
+use crate::spatial_hash::SpatialHash;
+use crate::species::Species;
+use crate::{Density, Particle};
+
+// ρ_i = m_i sum_j W(|x_i - x_j|), including the particle itself. Using the
+// particle's own mass rather than m_j keeps the density sharp across
+// interfaces between species.
+pub fn calculate_particle_density(hash: Res<SpatialHash>,
+                                  kernels: Res<Kernels>,
+                                  mut query: Query<(&mut Density, &Species), With<Particle>>) {
+    let masses: Vec<f32> = hash.entities
+        .iter()
+        .map(|entity| query.get(*entity).map_or(0.0, |(_, species)| species.mass))
+        .collect();
+
+    let densities: Vec<f32> = (0..hash.len())
+        .into_par_iter()
+        .map(|i| {
+            let position = hash.positions[i];
+            let mut number_density = 0.0;
+            hash.for_each_neighbour(position, kernels.density.radius(), |j| {
+                let distance = position.distance(hash.positions[j]);
+                number_density += kernels.density.value(distance);
+            });
+            masses[i] * number_density
+        })
+        .collect();
+
+    for (entity, value) in hash.entities.iter().zip(densities) {
+        if let Ok((mut density, _)) = query.get_mut(*entity) {
+            density.value = value;
+        }
+    }
+}
@@ -0,0 +1,338 @@
+use bevy::prelude::*;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::integrator::{Acceleration, Bounds, Force, LinearVelocity, Position};
+use crate::kernel::Kernels;
+use crate::spatial_hash::SpatialHash;
+use crate::species::Species;
+use crate::timestep::TimeStep;
+use crate::{Density, Particle};
+
+/// Bender & Koschier, Divergence-Free SPH (2015).
+#[derive(Resource, Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct DfsphSettings {
+    /// Tolerated average density error, relative to the rest density
+    pub max_density_error: f32,
+    /// Tolerated average density change per second, relative to the rest density
+    pub max_divergence_error: f32,
+    pub min_iterations: u32,
+    pub max_density_iterations: u32,
+    pub max_divergence_iterations: u32,
+    pub divergence_solve: bool,
+}
+
+impl Default for DfsphSettings {
+    fn default() -> Self {
+        DfsphSettings {
+            max_density_error: 0.01,
+            max_divergence_error: 0.1,
+            min_iterations: 2,
+            max_density_iterations: 100,
+            max_divergence_iterations: 100,
+            divergence_solve: true,
+        }
+    }
+}
+
+/// Iterations and final residuals of the last DFSPH step.
+#[derive(Resource, Clone, Copy, Debug, Default)]
+pub struct DfsphStats {
+    pub density_iterations: u32,
+    pub density_residual: f32,
+    pub divergence_iterations: u32,
+    pub divergence_residual: f32,
+}
+
+struct Solver<'a> {
+    hash: &'a SpatialHash,
+    kernels: &'a Kernels,
+    densities: Vec<f32>,
+    masses: Vec<f32>,
+    /// Each particle's species rest density ρ0_i
+    rest_densities: Vec<f32>,
+    factors: Vec<f32>,
+}
+
+impl Solver<'_> {
+    // α_i = ρ_i / (|sum_j m_j ∇W_ij|² + sum_j |m_j ∇W_ij|²)
+    fn compute_factors(&mut self) {
+        let hash = self.hash;
+        let kernel = &self.kernels.pressure;
+
+        self.factors = (0..hash.len())
+            .into_par_iter()
+            .map(|i| {
+                let mut gradient_i = Vec2::ZERO;
+                let mut gradient_sum = 0.0;
+                hash.for_each_neighbour(hash.positions[i], kernel.radius(), |j| {
+                    if i == j {
+                        return;
+                    }
+                    let gradient_j = self.masses[j] * kernel.gradient(hash.positions[i] - hash.positions[j]);
+                    gradient_i += gradient_j;
+                    gradient_sum += gradient_j.length_squared();
+                });
+
+                let denominator = gradient_i.length_squared() + gradient_sum;
+                if denominator > 1e-12 {
+                    self.densities[i] / denominator
+                } else {
+                    0.0
+                }
+            })
+            .collect();
+    }
+
+    // Dρ_i/Dt = sum_j m_j (v_i - v_j) · ∇W_ij
+    fn density_change(&self, velocities: &[Vec2]) -> Vec<f32> {
+        let hash = self.hash;
+        let kernel = &self.kernels.pressure;
+
+        (0..hash.len())
+            .into_par_iter()
+            .map(|i| {
+                let mut change = 0.0;
+                hash.for_each_neighbour(hash.positions[i], kernel.radius(), |j| {
+                    if i == j {
+                        return;
+                    }
+                    let gradient = kernel.gradient(hash.positions[i] - hash.positions[j]);
+                    change += self.masses[j] * (velocities[i] - velocities[j]).dot(gradient);
+                });
+                change
+            })
+            .collect()
+    }
+
+    // v_i -= dt * sum_j m_j (κ_i / ρ_i + κ_j / ρ_j) ∇W_ij
+    fn apply_stiffness(&self, velocities: &mut [Vec2], stiffness: &[f32], dt: f32) {
+        let hash = self.hash;
+        let kernel = &self.kernels.pressure;
+        let densities = &self.densities;
+
+        let corrections: Vec<Vec2> = (0..hash.len())
+            .into_par_iter()
+            .map(|i| {
+                let mut correction = Vec2::ZERO;
+                if densities[i] <= 0.0 {
+                    return correction;
+                }
+
+                hash.for_each_neighbour(hash.positions[i], kernel.radius(), |j| {
+                    if i == j || densities[j] <= 0.0 {
+                        return;
+                    }
+                    let gradient = kernel.gradient(hash.positions[i] - hash.positions[j]);
+                    correction += self.masses[j]
+                        * (stiffness[i] / densities[i] + stiffness[j] / densities[j])
+                        * gradient;
+                });
+                correction
+            })
+            .collect();
+
+        for (velocity, correction) in velocities.iter_mut().zip(corrections) {
+            *velocity -= dt * correction;
+        }
+    }
+
+    /// Average of `values`, each relative to its particle's rest density.
+    fn relative_average(&self, values: &[f32]) -> f32 {
+        let relative: Vec<f32> = values.iter().zip(&self.rest_densities).map(|(value, rest)| value / rest).collect();
+        average(&relative)
+    }
+
+    /// Makes the velocity field divergence free. Returns iterations and the
+    /// average relative density change per second.
+    fn correct_divergence(&self, velocities: &mut [Vec2], dt: f32, settings: &DfsphSettings) -> (u32, f32) {
+        let mut iterations = 0;
+        let mut residual = 0.0;
+
+        while iterations < settings.max_divergence_iterations {
+            // Only compression is corrected, so free surfaces may expand
+            let change: Vec<f32> = self.density_change(velocities)
+                .into_iter()
+                .map(|change| change.max(0.0))
+                .collect();
+            residual = self.relative_average(&change);
+
+            if iterations >= settings.min_iterations && residual <= settings.max_divergence_error {
+                break;
+            }
+
+            let stiffness: Vec<f32> = change
+                .iter()
+                .zip(&self.factors)
+                .map(|(change, factor)| change / dt * factor)
+                .collect();
+            self.apply_stiffness(velocities, &stiffness, dt);
+            iterations += 1;
+        }
+
+        (iterations, residual)
+    }
+
+    /// Corrects the predicted velocities so that advecting by them reaches the
+    /// rest density. Returns iterations and the average relative density error.
+    fn correct_density(&self, velocities: &mut [Vec2], dt: f32, settings: &DfsphSettings) -> (u32, f32) {
+        let mut iterations = 0;
+        let mut residual = 0.0;
+
+        while iterations < settings.max_density_iterations {
+            let error: Vec<f32> = self.density_change(velocities)
+                .into_iter()
+                .zip(self.densities.iter().zip(&self.rest_densities))
+                .map(|(change, (density, rest_density))| (density + dt * change - rest_density).max(0.0))
+                .collect();
+            residual = self.relative_average(&error);
+
+            if iterations >= settings.min_iterations && residual <= settings.max_density_error {
+                break;
+            }
+
+            let stiffness: Vec<f32> = error
+                .iter()
+                .zip(&self.factors)
+                .map(|(error, factor)| error / dt.powi(2) * factor)
+                .collect();
+            self.apply_stiffness(velocities, &stiffness, dt);
+            iterations += 1;
+        }
+
+        (iterations, residual)
+    }
+}
+
+fn average(values: &[f32]) -> f32 {
+    if values.is_empty() {
+        return 0.0;
+    }
+    values.iter().sum::<f32>() / values.len() as f32
+}
+
+// The usual DFSPH loop rotated to start with the neighbourhood and densities
+// computed for this step: divergence solve, non-pressure forces, density
+// solve, then advection.
+pub fn dfsph_step(hash: Res<SpatialHash>,
+                  kernels: Res<Kernels>,
+                  settings: Res<DfsphSettings>,
+                  bounds: Res<Bounds>,
+                  timestep: Res<TimeStep>,
+                  mut stats: ResMut<DfsphStats>,
+                  mut query: Query<(&mut Position,
+                                    &mut LinearVelocity,
+                                    &mut Acceleration,
+                                    &mut Transform,
+                                    &Density,
+                                    &Force,
+                                    &Species), With<Particle>>) {
+    let dt = timestep.dt;
+    if dt <= 0.0 {
+        return;
+    }
+
+    let mut old_velocities = Vec::with_capacity(hash.len());
+    let mut forces = Vec::with_capacity(hash.len());
+    let mut densities = Vec::with_capacity(hash.len());
+    let mut masses = Vec::with_capacity(hash.len());
+    let mut rest_densities = Vec::with_capacity(hash.len());
+    for entity in hash.entities.iter() {
+        let (velocity, force, density, mass, rest_density) = query
+            .get(*entity)
+            .map_or((Vec2::ZERO, Vec2::ZERO, 0.0, 1.0, 1.0), |(_, velocity, _, _, density, force, species)| {
+                (velocity.0, force.0, density.value, species.mass, species.rest_density)
+            });
+        old_velocities.push(velocity);
+        forces.push(force);
+        densities.push(density);
+        masses.push(mass);
+        rest_densities.push(rest_density);
+    }
+
+    let mut solver = Solver {
+        hash: &hash,
+        kernels: &kernels,
+        densities,
+        masses,
+        rest_densities,
+        factors: Vec::new(),
+    };
+    solver.compute_factors();
+
+    let mut velocities = old_velocities.clone();
+    if settings.divergence_solve {
+        let (iterations, residual) = solver.correct_divergence(&mut velocities, dt, &settings);
+        stats.divergence_iterations = iterations;
+        stats.divergence_residual = residual;
+    }
+
+    for ((velocity, force), mass) in velocities.iter_mut().zip(&forces).zip(&solver.masses) {
+        *velocity += dt * *force / *mass;
+    }
+
+    let (iterations, residual) = solver.correct_density(&mut velocities, dt, &settings);
+    stats.density_iterations = iterations;
+    stats.density_residual = residual;
+
+    for (i, entity) in hash.entities.iter().enumerate() {
+        if let Ok((mut position, mut velocity, mut acceleration, mut transform, _, _, _)) = query.get_mut(*entity) {
+            acceleration.0 = (velocities[i] - old_velocities[i]) / dt;
+            velocity.0 = velocities[i];
+            position.0 += dt * velocity.0;
+
+            bounds.resolve(&mut position.0, &mut velocity.0);
+            transform.translation.x = position.0.x;
+            transform.translation.y = position.0.y;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::ecs::system::RunSystemOnce;
+
+    use crate::density::calculate_particle_density;
+    use crate::kernel::KernelTypes;
+
+    #[test]
+    fn converges_on_a_compressed_block() {
+        let h = 75.0;
+        let settings = DfsphSettings::default();
+        let mut world = World::new();
+        world.insert_resource(KernelTypes::default().build(h));
+        world.insert_resource(settings);
+        world.insert_resource(Bounds::new(2000.0, 2000.0, 0.0));
+        world.insert_resource(TimeStep { dt: 1.0 / 60.0, ..default() });
+        world.insert_resource(DfsphStats::default());
+
+        // Rest spacing of h / 2, packed at 33 px
+        let species = Species { mass: 3e-4 * 37.5_f32.powi(2), ..Species::water() };
+        let mut particles = Vec::new();
+        for i in 0..400 {
+            let position = 33.0 * Vec2::new((i % 20) as f32, (i / 20) as f32);
+            let entity = world.spawn((Particle,
+                                      Position(position),
+                                      LinearVelocity::default(),
+                                      Acceleration::default(),
+                                      Transform::default(),
+                                      Density { value: 0.0 },
+                                      Force::default(),
+                                      species.clone())).id();
+            particles.push((entity, position));
+        }
+        let mut hash = SpatialHash::new(h);
+        hash.rebuild(particles.into_iter());
+        world.insert_resource(hash);
+
+        world.run_system_once(calculate_particle_density);
+        world.run_system_once(dfsph_step);
+
+        let stats = *world.resource::<DfsphStats>();
+        assert!(stats.density_iterations >= settings.min_iterations, "{stats:?}");
+        assert!(stats.density_iterations < settings.max_density_iterations, "{stats:?}");
+        assert!(stats.divergence_iterations <= settings.max_divergence_iterations, "{stats:?}");
+        assert!(stats.density_residual <= settings.max_density_error, "{stats:?}");
+    }
+}
@@ -0,0 +1,242 @@
+use std::f32::consts::PI;
+
+use bevy::prelude::*;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::barnes_hut::QuadTree;
+use crate::integrator::Force;
+use crate::spatial_hash::SpatialHash;
+use crate::Particle;
+
+/// Signed charge of a particle, 0 for neutral.
+#[derive(Component, Clone, Copy, Debug, Default, PartialEq)]
+pub struct Charge(pub f32);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CoulombMethod {
+    /// Exact sum over all pairs, O(n²)
+    Direct,
+    /// Pairs within the cutoff, shifted so the force goes to zero there
+    Cutoff,
+    /// Wolf et al. damped shifted force: the real space part of an Ewald sum,
+    /// with the cutoff chosen so the damped tail is negligible
+    Wolf,
+    /// Barnes–Hut quadtree with opening angle θ, O(n log n) without a cutoff
+    BarnesHut,
+}
+
+#[derive(Resource, Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct ElectrostaticSettings {
+    pub method: CoulombMethod,
+    /// k in F = k q_i q_j / r²
+    pub coulomb_constant: f32,
+    /// Plummer softening length ε, r² becomes r² + ε²
+    pub softening: f32,
+    pub cutoff: f32,
+    /// Wolf only: damping α, usually with α × cutoff between 2 and 3
+    pub damping: f32,
+    /// Barnes–Hut only: nodes of size s at distance d are opened when s / d ≥ θ
+    pub theta: f32,
+    /// Also compute the exact all-pairs sum each step and record the error
+    /// of `method` against it in `CoulombAccuracy`
+    pub compare_exact: bool,
+}
+
+impl Default for ElectrostaticSettings {
+    fn default() -> Self {
+        ElectrostaticSettings {
+            method: CoulombMethod::Wolf,
+            coulomb_constant: 1e7,
+            softening: 5.0,
+            cutoff: 300.0,
+            damping: 0.008,
+            theta: 0.5,
+            compare_exact: false,
+        }
+    }
+}
+
+impl ElectrostaticSettings {
+    /// Magnitude of the force between unit charges at distance r, positive
+    /// when repulsive.
+    pub fn force(&self, r: f32) -> f32 {
+        match self.method {
+            CoulombMethod::Direct | CoulombMethod::BarnesHut => self.softened(r),
+            CoulombMethod::Cutoff => {
+                if r > self.cutoff {
+                    return 0.0;
+                }
+                self.softened(r) - self.softened(self.cutoff)
+            }
+            CoulombMethod::Wolf => {
+                if r > self.cutoff {
+                    return 0.0;
+                }
+                self.damped(r) - self.damped(self.cutoff)
+            }
+        }
+    }
+
+    fn softened_distance(&self, r: f32) -> f32 {
+        (r.powi(2) + self.softening.powi(2)).sqrt()
+    }
+
+    fn softened(&self, r: f32) -> f32 {
+        // k r / (r² + ε²)^(3/2), the gradient of the softened potential
+        self.coulomb_constant * r / self.softened_distance(r).powi(3)
+    }
+
+    // k (erfc(αr) / r² + 2α / √π exp(-α²r²) / r), with the softened distance
+    // standing in for r
+    fn damped(&self, r: f32) -> f32 {
+        let s = self.softened_distance(r);
+        let alpha = self.damping;
+        let magnitude = erfc(alpha * s) / s.powi(2)
+            + 2.0 * alpha / PI.sqrt() * (-(alpha * s).powi(2)).exp() / s;
+        self.coulomb_constant * magnitude * r / s
+    }
+}
+
+// Abramowitz & Stegun 7.1.26, absolute error below 1.5e-7
+fn erfc(x: f32) -> f32 {
+    if x < 0.0 {
+        return 2.0 - erfc(-x);
+    }
+
+    let t = 1.0 / (1.0 + 0.3275911 * x);
+    let polynomial = t * (0.2548296
+        + t * (-0.28449672 + t * (1.4214138 + t * (-1.4531521 + t * 1.0614054))));
+    polynomial * (-x * x).exp()
+}
+
+/// Error of the configured method against the exact all-pairs sum, recorded
+/// when `ElectrostaticSettings::compare_exact` is set.
+#[derive(Resource, Clone, Copy, Debug, Default)]
+pub struct CoulombAccuracy {
+    /// sum_i |F_i - F_i,exact| / sum_i |F_i,exact|
+    pub mean_relative_error: f32,
+    /// Largest |F_i - F_i,exact| / |F_i,exact|
+    pub max_relative_error: f32,
+}
+
+// F_i = sum_j k q_i q_j f(r_ij) (x_i - x_j) / r_ij, so like charges repel
+pub fn coulomb_force_system(hash: Res<SpatialHash>,
+                            settings: Res<ElectrostaticSettings>,
+                            mut accuracy: ResMut<CoulombAccuracy>,
+                            mut query: Query<(&mut Force, &Charge), With<Particle>>) {
+    let charges: Vec<f32> = hash.entities
+        .iter()
+        .map(|entity| query.get(*entity).map_or(0.0, |(_, charge)| charge.0))
+        .collect();
+    if charges.iter().all(|charge| *charge == 0.0) {
+        return;
+    }
+
+    let forces = coulomb_forces(&hash, &charges, &settings);
+
+    if settings.compare_exact {
+        let exact_settings = ElectrostaticSettings { method: CoulombMethod::Direct, ..*settings };
+        let exact = coulomb_forces(&hash, &charges, &exact_settings);
+
+        let (mut error, mut magnitude, mut max_relative_error) = (0.0, 0.0, 0.0_f32);
+        for (force, exact) in forces.iter().zip(&exact) {
+            let difference = force.distance(*exact);
+            error += difference;
+            magnitude += exact.length();
+            if exact.length() > 0.0 {
+                max_relative_error = max_relative_error.max(difference / exact.length());
+            }
+        }
+        *accuracy = CoulombAccuracy {
+            mean_relative_error: if magnitude > 0.0 { error / magnitude } else { 0.0 },
+            max_relative_error,
+        };
+    }
+
+    for (entity, value) in hash.entities.iter().zip(forces) {
+        if let Ok((mut force, _)) = query.get_mut(*entity) {
+            force.0 += value;
+        }
+    }
+}
+
+fn coulomb_forces(hash: &SpatialHash, charges: &[f32], settings: &ElectrostaticSettings) -> Vec<Vec2> {
+    let law = |r: f32| settings.force(r);
+
+    if settings.method == CoulombMethod::BarnesHut {
+        let tree = QuadTree::new(&hash.positions, charges);
+        return (0..hash.len())
+            .into_par_iter()
+            .map(|i| {
+                if charges[i] == 0.0 {
+                    return Vec2::ZERO;
+                }
+                charges[i] * tree.accumulate(hash.positions[i], i, settings.theta, law)
+            })
+            .collect();
+    }
+
+    (0..hash.len())
+        .into_par_iter()
+        .map(|i| {
+            let mut force = Vec2::ZERO;
+            if charges[i] == 0.0 {
+                return force;
+            }
+
+            let add = |j: usize| {
+                if i == j || charges[j] == 0.0 {
+                    return;
+                }
+                let offset = hash.positions[i] - hash.positions[j];
+                let distance = offset.length();
+                if distance <= f32::EPSILON {
+                    return;
+                }
+                force += charges[i] * charges[j] * law(distance) * offset / distance;
+            };
+
+            match settings.method {
+                CoulombMethod::Direct | CoulombMethod::BarnesHut => (0..hash.len()).for_each(add),
+                CoulombMethod::Cutoff | CoulombMethod::Wolf => {
+                    hash.for_each_neighbour(hash.positions[i], settings.cutoff, add)
+                }
+            }
+            force
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn erfc_matches_reference_values() {
+        for (x, expected) in [(0.0, 1.0), (0.5, 0.4795001), (1.0, 0.1572992), (2.0, 0.0046777), (-1.0, 1.8427008)] {
+            assert!((erfc(x) - expected).abs() < 1e-6, "erfc({x}) = {}", erfc(x));
+        }
+    }
+
+    #[test]
+    fn truncated_forces_vanish_at_the_cutoff() {
+        for method in [CoulombMethod::Cutoff, CoulombMethod::Wolf] {
+            let settings = ElectrostaticSettings { method, ..default() };
+            assert!(settings.force(settings.cutoff).abs() < 1e-6);
+            assert_eq!(settings.force(settings.cutoff + 1.0), 0.0);
+            assert!(settings.force(10.0) > settings.force(100.0));
+        }
+    }
+
+    #[test]
+    fn softening_bounds_the_force() {
+        let settings = ElectrostaticSettings { method: CoulombMethod::Direct, ..default() };
+        // k r / (r² + ε²)^(3/2) peaks at r = ε / √2
+        let peak = settings.coulomb_constant * 2.0 / (3.0_f32.sqrt() * 3.0 * settings.softening.powi(2));
+        for r in [1e-3, 1.0, 3.0, 5.0, 50.0] {
+            assert!(settings.force(r) <= peak * 1.0001);
+        }
+        assert!((settings.force(1000.0) - settings.coulomb_constant / 1000.0_f32.powi(2)).abs() < 1e-3);
+    }
+}
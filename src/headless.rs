@@ -0,0 +1,83 @@
+use bevy::app::AppExit;
+use bevy::prelude::*;
+
+use crate::snapshot::capture_snapshot;
+
+/// Present when running without a window. The app advances exactly one fixed
+/// step per frame, and after `frames` steps writes the final state to `output`
+/// as a snapshot and exits. A failed write is logged and kept in `error`, for
+/// the app to report once it exits.
+#[derive(Resource, Clone, Debug)]
+pub struct Headless {
+    pub frames: u32,
+    /// `.bin` for the binary snapshot format, JSON otherwise
+    pub output: String,
+    /// Fixed steps taken so far
+    pub frame: u32,
+    /// Why the results could not be written, if they could not
+    pub error: Option<String>,
+}
+
+pub fn finish_headless_run(world: &mut World) {
+    let mut headless = world.resource_mut::<Headless>();
+    headless.frame += 1;
+    if headless.frame < headless.frames {
+        return;
+    }
+
+    let (frames, output) = (headless.frames, headless.output.clone());
+    let snapshot = capture_snapshot(world);
+    match snapshot.save(&output) {
+        Ok(()) => info!("Wrote {} particles after {frames} frames to {output}", snapshot.particles.len()),
+        Err(error) => {
+            let error = format!("Could not write results to {output}: {error}");
+            error!("{error}");
+            world.resource_mut::<Headless>().error = Some(error);
+        }
+    }
+    world.send_event(AppExit);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::SimConfig;
+    use crate::snapshot::Snapshot;
+    use crate::FluidSimPlugin;
+
+    #[test]
+    fn writes_results_and_exits() {
+        let output = std::env::temp_dir().join(format!("chem-headless-{}.json", std::process::id()));
+        let config = SimConfig { n_particles: 16, ..default() };
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, TransformPlugin, HierarchyPlugin))
+            .add_plugins(FluidSimPlugin::new().with_config(config).headless(5, 0.01, output.to_str().unwrap()));
+
+        let mut updates = 0;
+        while app.world.resource::<Events<AppExit>>().is_empty() {
+            assert!(updates < 20, "no AppExit after {updates} updates");
+            app.update();
+            updates += 1;
+        }
+
+        assert_eq!(app.world.resource::<Headless>().frame, 5);
+        let snapshot = Snapshot::load(&output).unwrap();
+        std::fs::remove_file(&output).unwrap();
+        assert_eq!(snapshot.particles.len(), 16);
+        assert_eq!(app.world.resource::<Headless>().error, None);
+    }
+
+    #[test]
+    fn failed_writes_are_kept_for_the_app() {
+        let output = std::env::temp_dir().join("chem-missing-directory").join("results.json");
+        let config = SimConfig { n_particles: 16, ..default() };
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, TransformPlugin, HierarchyPlugin))
+            .add_plugins(FluidSimPlugin::new().with_config(config).headless(2, 0.01, output.to_str().unwrap()));
+
+        while app.world.resource::<Events<AppExit>>().is_empty() {
+            app.update();
+        }
+        assert!(app.world.resource::<Headless>().error.is_some());
+    }
+}
@@ -0,0 +1,214 @@
+use bevy::prelude::*;
+use bevy_prototype_lyon::prelude::*;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::integrator::Bounds;
+use crate::kernel::Kernels;
+use crate::spatial_hash::SpatialHash;
+use crate::species::Species;
+use crate::timestep::TimeStep;
+use crate::{Cell, Density, Particle};
+
+/// Particle temperature in kelvin.
+#[derive(Component, Clone, Copy, Debug, PartialEq)]
+pub struct Temperature(pub f32);
+
+/// Temperature particles start at.
+#[derive(Resource, Clone, Copy, Debug)]
+pub struct AmbientTemperature(pub f32);
+
+impl Default for AmbientTemperature {
+    fn default() -> Self {
+        AmbientTemperature(293.15)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum WallTemperature {
+    Insulated,
+    /// Held at a temperature, exchanging heat with particles within h / 2
+    Fixed(f32),
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TemperatureDisplay {
+    /// Particles show their species and cells their density
+    #[default]
+    Off,
+    Particles,
+    Cells,
+}
+
+#[derive(Resource, Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct HeatSettings {
+    pub left: WallTemperature,
+    pub right: WallTemperature,
+    pub bottom: WallTemperature,
+    pub top: WallTemperature,
+    pub display: TemperatureDisplay,
+    /// Temperatures mapped to the ends of the colour scale
+    pub cold: f32,
+    pub hot: f32,
+}
+
+impl Default for HeatSettings {
+    fn default() -> Self {
+        HeatSettings {
+            left: WallTemperature::Insulated,
+            right: WallTemperature::Insulated,
+            bottom: WallTemperature::Insulated,
+            top: WallTemperature::Insulated,
+            display: TemperatureDisplay::Off,
+            cold: 273.15,
+            hot: 373.15,
+        }
+    }
+}
+
+impl HeatSettings {
+    pub fn colour(&self, temperature: f32) -> Color {
+        let t = ((temperature - self.cold) / (self.hot - self.cold)).clamp(0.0, 1.0);
+        Color::rgb(t, 0.2, 1.0 - t)
+    }
+
+    // Each fixed wall as (temperature, distance of `position` from it)
+    fn walls(&self, bounds: &Bounds, position: Vec2) -> [(WallTemperature, f32); 4] {
+        [
+            (self.left, position.x - bounds.min.x),
+            (self.right, bounds.max.x - position.x),
+            (self.bottom, position.y - bounds.min.y),
+            (self.top, bounds.max.y - position.y),
+        ]
+    }
+}
+
+pub fn displaying_particle_temperature(settings: Res<HeatSettings>) -> bool {
+    settings.display == TemperatureDisplay::Particles
+}
+
+pub fn displaying_cell_temperature(settings: Res<HeatSettings>) -> bool {
+    settings.display == TemperatureDisplay::Cells
+}
+
+// Cleary & Monaghan conduction with a harmonic mean of the conductivities,
+// which keeps the flux continuous across interfaces between species:
+// c_i dT_i/dt = sum_j m_j / (ρ_i ρ_j) 4 k_i k_j / (k_i + k_j)
+//               (T_i - T_j) r_ij · ∇W_ij / (r_ij² + 0.01h²)
+// Fixed walls act as a mirrored ghost of the particle at the wall temperature.
+pub fn heat_conduction_system(hash: Res<SpatialHash>,
+                              kernels: Res<Kernels>,
+                              settings: Res<HeatSettings>,
+                              bounds: Res<Bounds>,
+                              timestep: Res<TimeStep>,
+                              mut query: Query<(&mut Temperature, &Density, &Species), With<Particle>>) {
+    let dt = timestep.dt;
+    if dt <= 0.0 {
+        return;
+    }
+
+    let mut temperatures = Vec::with_capacity(hash.len());
+    let mut densities = Vec::with_capacity(hash.len());
+    let mut species = Vec::with_capacity(hash.len());
+    for entity in hash.entities.iter() {
+        let (temperature, density, properties) = query
+            .get(*entity)
+            .map_or((0.0, 0.0, (0.0, 0.0, 0.0)), |(temperature, density, species)| {
+                (temperature.0, density.value, (species.mass, species.conductivity, species.heat_capacity))
+            });
+        temperatures.push(temperature);
+        densities.push(density);
+        species.push(properties);
+    }
+
+    let kernel = &kernels.pressure;
+    let h = kernel.radius();
+    let conduction = |offset: Vec2, mass: f32, density_j: f32, conductivity: f32, difference: f32| {
+        let distance_squared = offset.length_squared();
+        mass * conductivity * difference * offset.dot(kernel.gradient(offset))
+            / (density_j * (distance_squared + 0.01 * h.powi(2)))
+    };
+
+    let rates: Vec<f32> = (0..hash.len())
+        .into_par_iter()
+        .map(|i| {
+            let (mass_i, conductivity_i, heat_capacity_i) = species[i];
+            if densities[i] <= 0.0 || heat_capacity_i <= 0.0 {
+                return 0.0;
+            }
+
+            let mut rate = 0.0;
+            hash.for_each_neighbour(hash.positions[i], h, |j| {
+                let (mass_j, conductivity_j, _) = species[j];
+                if i == j || densities[j] <= 0.0 || conductivity_i + conductivity_j <= 0.0 {
+                    return;
+                }
+                let conductivity = 4.0 * conductivity_i * conductivity_j / (conductivity_i + conductivity_j);
+                rate += conduction(hash.positions[i] - hash.positions[j], mass_j, densities[j],
+                                   conductivity, temperatures[i] - temperatures[j]);
+            });
+
+            for (wall, distance) in settings.walls(&bounds, hash.positions[i]) {
+                if let WallTemperature::Fixed(wall_temperature) = wall {
+                    if 2.0 * distance < h {
+                        // Only the distance to the ghost matters, not its direction.
+                        // Particles resting on the wall still exchange heat.
+                        let offset = Vec2::new((2.0 * distance).max(0.1 * h), 0.0);
+                        rate += conduction(offset, mass_i, densities[i], 2.0 * conductivity_i,
+                                           temperatures[i] - wall_temperature);
+                    }
+                }
+            }
+
+            rate / (densities[i] * heat_capacity_i)
+        })
+        .collect();
+
+    for (entity, rate) in hash.entities.iter().zip(rates) {
+        if let Ok((mut temperature, _, _)) = query.get_mut(*entity) {
+            temperature.0 += dt * rate;
+        }
+    }
+}
+
+pub fn colour_particles_by_temperature(settings: Res<HeatSettings>,
+                                       mut query: Query<(&Temperature, &mut Fill), With<Particle>>) {
+    for (temperature, mut fill) in query.iter_mut() {
+        let colour = settings.colour(temperature.0);
+        if fill.color != colour {
+            fill.color = colour;
+        }
+    }
+}
+
+// Shepard interpolation T = sum_j V_j T_j W / sum_j V_j W, so cells at the edge
+// of the fluid are not biased towards zero. Runs after the density colouring
+// and paints over it.
+pub fn calculate_cell_temperature(hash: Res<SpatialHash>,
+                                  kernels: Res<Kernels>,
+                                  settings: Res<HeatSettings>,
+                                  particles: Query<(&Temperature, &Density, &Species), With<Particle>>,
+                                  mut cells: Query<(&Transform, &mut Cell, &mut Fill)>) {
+    let samples: Vec<(f32, f32)> = hash.entities
+        .iter()
+        .map(|entity| {
+            particles.get(*entity).map_or((0.0, 0.0), |(temperature, density, species)| {
+                let volume = if density.value > 0.0 { species.mass / density.value } else { 0.0 };
+                (temperature.0, volume)
+            })
+        })
+        .collect();
+
+    cells.par_iter_mut().for_each(|(transform, mut cell, mut fill)| {
+        let centre = transform.translation.truncate();
+        let (mut weighted, mut total) = (0.0, 0.0);
+        hash.for_each_neighbour(centre, kernels.density.radius(), |j| {
+            let (temperature, volume) = samples[j];
+            let weight = volume * kernels.density.value(centre.distance(hash.positions[j]));
+            weighted += weight * temperature;
+            total += weight;
+        });
+        cell.temperature = if total > 0.0 { weighted / total } else { 0.0 };
+        cell.update_cell_temperature_colour(&mut fill, &settings);
+    });
+}
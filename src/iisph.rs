@@ -0,0 +1,281 @@
+use bevy::prelude::*;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::integrator::{Acceleration, Bounds, Force, LinearVelocity, Position};
+use crate::kernel::Kernels;
+use crate::spatial_hash::SpatialHash;
+use crate::species::Species;
+use crate::timestep::TimeStep;
+use crate::{Density, Particle, Pressure};
+
+/// Ihmsen et al., Implicit Incompressible SPH (2014).
+#[derive(Resource, Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct IisphSettings {
+    /// Relaxation ω of the Jacobi iterations. 0.5 suits kernels about twice
+    /// the particle spacing; much wider ones need less to converge.
+    pub omega: f32,
+    /// Tolerated average density error, relative to the rest density
+    pub max_density_error: f32,
+    pub min_iterations: u32,
+    pub max_iterations: u32,
+}
+
+impl Default for IisphSettings {
+    fn default() -> Self {
+        IisphSettings {
+            omega: 0.5,
+            max_density_error: 0.01,
+            min_iterations: 2,
+            max_iterations: 100,
+        }
+    }
+}
+
+/// Average relative density error after each Jacobi iteration of the last
+/// IISPH step.
+#[derive(Resource, Clone, Debug, Default)]
+pub struct IisphConvergence {
+    pub history: Vec<f32>,
+}
+
+impl IisphConvergence {
+    pub fn iterations(&self) -> usize {
+        self.history.len()
+    }
+
+    pub fn residual(&self) -> Option<f32> {
+        self.history.last().copied()
+    }
+}
+
+pub fn iisph_step(hash: Res<SpatialHash>,
+                  kernels: Res<Kernels>,
+                  settings: Res<IisphSettings>,
+                  bounds: Res<Bounds>,
+                  timestep: Res<TimeStep>,
+                  mut convergence: ResMut<IisphConvergence>,
+                  mut query: Query<(&mut Position,
+                                    &mut LinearVelocity,
+                                    &mut Acceleration,
+                                    &mut Pressure,
+                                    &mut Transform,
+                                    &Density,
+                                    &Force,
+                                    &Species), With<Particle>>) {
+    let dt = timestep.dt;
+    if dt <= 0.0 {
+        return;
+    }
+
+    let kernel = &kernels.pressure;
+    let h = kernel.radius();
+
+    let mut old_velocities = Vec::with_capacity(hash.len());
+    let mut advected = Vec::with_capacity(hash.len());
+    let mut densities = Vec::with_capacity(hash.len());
+    let mut pressures = Vec::with_capacity(hash.len());
+    let mut masses = Vec::with_capacity(hash.len());
+    let mut rest_densities = Vec::with_capacity(hash.len());
+    for entity in hash.entities.iter() {
+        let (velocity, force, density, pressure, mass, rest_density) = query
+            .get(*entity)
+            .map_or((Vec2::ZERO, Vec2::ZERO, 0.0, 0.0, 1.0, 1.0), |(_, velocity, _, pressure, _, density, force, species)| {
+                (velocity.0, force.0, density.value, pressure.value, species.mass, species.rest_density)
+            });
+        old_velocities.push(velocity);
+        advected.push(velocity + dt * force / mass);
+        densities.push(density);
+        masses.push(mass);
+        rest_densities.push(rest_density);
+        // Warm start from half of last step's pressure
+        pressures.push(0.5 * pressure);
+    }
+
+    let gradient = |i: usize, j: usize| kernel.gradient(hash.positions[i] - hash.positions[j]);
+    let dt2 = dt.powi(2);
+
+    // d_ii = -dt² sum_j m_j / ρ_i² ∇W_ij and the advected density
+    // ρ_adv,i = ρ_i + dt sum_j m_j (v_adv,i - v_adv,j) · ∇W_ij
+    let (displacements, advected_densities): (Vec<Vec2>, Vec<f32>) = (0..hash.len())
+        .into_par_iter()
+        .map(|i| {
+            if densities[i] <= 0.0 {
+                return (Vec2::ZERO, 0.0);
+            }
+
+            let mut displacement = Vec2::ZERO;
+            let mut density = densities[i];
+            hash.for_each_neighbour(hash.positions[i], h, |j| {
+                if i == j {
+                    return;
+                }
+                let w = gradient(i, j);
+                displacement -= dt2 * masses[j] / densities[i].powi(2) * w;
+                density += dt * masses[j] * (advected[i] - advected[j]).dot(w);
+            });
+            (displacement, density)
+        })
+        .unzip();
+
+    // a_ii = sum_j m_j (d_ii - d_ji) · ∇W_ij with d_ji = dt² m_i / ρ_i² ∇W_ij
+    let diagonal: Vec<f32> = (0..hash.len())
+        .into_par_iter()
+        .map(|i| {
+            let mut a = 0.0;
+            if densities[i] <= 0.0 {
+                return a;
+            }
+            hash.for_each_neighbour(hash.positions[i], h, |j| {
+                if i == j {
+                    return;
+                }
+                let w = gradient(i, j);
+                let d_ji = dt2 * masses[i] / densities[i].powi(2) * w;
+                a += masses[j] * (displacements[i] - d_ji).dot(w);
+            });
+            a
+        })
+        .collect();
+
+    convergence.history.clear();
+    let mut iterations = 0;
+
+    while iterations < settings.max_iterations {
+        // sum_j d_ij p_j = -dt² sum_j m_j / ρ_j² p_j ∇W_ij
+        let pressure_displacements: Vec<Vec2> = (0..hash.len())
+            .into_par_iter()
+            .map(|i| {
+                let mut displacement = Vec2::ZERO;
+                hash.for_each_neighbour(hash.positions[i], h, |j| {
+                    if i == j || densities[j] <= 0.0 {
+                        return;
+                    }
+                    displacement -= dt2 * masses[j] / densities[j].powi(2) * pressures[j] * gradient(i, j);
+                });
+                displacement
+            })
+            .collect();
+
+        let (updated, errors): (Vec<f32>, Vec<f32>) = (0..hash.len())
+            .into_par_iter()
+            .map(|i| {
+                if densities[i] <= 0.0 || diagonal[i] == 0.0 {
+                    return (0.0, 0.0);
+                }
+
+                let mut sum = 0.0;
+                hash.for_each_neighbour(hash.positions[i], h, |j| {
+                    if i == j {
+                        return;
+                    }
+                    let w = gradient(i, j);
+                    let d_ji = dt2 * masses[i] / densities[i].powi(2) * w;
+                    let others = pressure_displacements[j] - d_ji * pressures[i];
+                    sum += masses[j] * (pressure_displacements[i] - displacements[j] * pressures[j] - others).dot(w);
+                });
+
+                let source = rest_densities[i] - advected_densities[i];
+                let error = (diagonal[i] * pressures[i] + sum - source).max(0.0) / rest_densities[i];
+                let pressure = (1.0 - settings.omega) * pressures[i]
+                    + settings.omega / diagonal[i] * (source - sum);
+                (pressure.max(0.0), error)
+            })
+            .unzip();
+
+        pressures = updated;
+        iterations += 1;
+
+        let residual = errors.iter().sum::<f32>() / errors.len().max(1) as f32;
+        convergence.history.push(residual);
+        if iterations >= settings.min_iterations && residual <= settings.max_density_error {
+            break;
+        }
+    }
+
+    // F_p = -m_i sum_j m_j (p_i / ρ_i² + p_j / ρ_j²) ∇W_ij
+    let velocities: Vec<Vec2> = (0..hash.len())
+        .into_par_iter()
+        .map(|i| {
+            let mut acceleration = Vec2::ZERO;
+            if densities[i] <= 0.0 {
+                return advected[i];
+            }
+            hash.for_each_neighbour(hash.positions[i], h, |j| {
+                if i == j || densities[j] <= 0.0 {
+                    return;
+                }
+                acceleration -= masses[j]
+                    * (pressures[i] / densities[i].powi(2) + pressures[j] / densities[j].powi(2))
+                    * gradient(i, j);
+            });
+            advected[i] + dt * acceleration
+        })
+        .collect();
+
+    for (i, entity) in hash.entities.iter().enumerate() {
+        if let Ok((mut position, mut velocity, mut acceleration, mut pressure, mut transform, _, _, _)) =
+            query.get_mut(*entity) {
+            acceleration.0 = (velocities[i] - old_velocities[i]) / dt;
+            velocity.0 = velocities[i];
+            position.0 += dt * velocity.0;
+            pressure.value = pressures[i];
+
+            bounds.resolve(&mut position.0, &mut velocity.0);
+            transform.translation.x = position.0.x;
+            transform.translation.y = position.0.y;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::ecs::system::RunSystemOnce;
+
+    use crate::density::calculate_particle_density;
+    use crate::kernel::KernelTypes;
+
+    #[test]
+    fn converges_on_a_compressed_block() {
+        let h = 75.0;
+        let settings = IisphSettings::default();
+        let mut world = World::new();
+        world.insert_resource(KernelTypes::default().build(h));
+        world.insert_resource(settings);
+        world.insert_resource(Bounds::new(2000.0, 2000.0, 0.0));
+        world.insert_resource(TimeStep { dt: 1.0 / 60.0, ..default() });
+        world.insert_resource(IisphConvergence::default());
+
+        // Rest spacing of h / 2, packed at 33 px
+        let species = Species { mass: 3e-4 * 37.5_f32.powi(2), ..Species::water() };
+        let mut particles = Vec::new();
+        for i in 0..400 {
+            let position = 33.0 * Vec2::new((i % 20) as f32, (i / 20) as f32);
+            let entity = world.spawn((Particle,
+                                      Position(position),
+                                      LinearVelocity::default(),
+                                      Acceleration::default(),
+                                      Pressure { value: 0.0 },
+                                      Transform::default(),
+                                      Density { value: 0.0 },
+                                      Force::default(),
+                                      species.clone())).id();
+            particles.push((entity, position));
+        }
+        let mut hash = SpatialHash::new(h);
+        hash.rebuild(particles.into_iter());
+        world.insert_resource(hash);
+
+        world.run_system_once(calculate_particle_density);
+        world.run_system_once(iisph_step);
+
+        let history = &world.resource::<IisphConvergence>().history;
+        assert!(!history.is_empty());
+        assert!(history.len() <= settings.max_iterations as usize);
+        let increases = history.windows(2).filter(|pair| pair[1] > pair[0]).count();
+        assert!(increases * 10 <= history.len(), "{history:?}");
+        assert!(history[0] > settings.max_density_error, "{history:?}");
+        assert!(history[history.len() - 1] <= settings.max_density_error, "{history:?}");
+    }
+}
@@ -0,0 +1,223 @@
+use bevy::prelude::*;
+use bevy_rapier2d::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::pressure::PressureSolver;
+use crate::species::Species;
+use crate::timestep::TimeStep;
+use crate::Particle;
+
+/// Who advances particle positions under the force-based solver. With
+/// `Rapier`, particles are dynamic bodies driven through `ExternalForce`;
+/// otherwise they are kinematic bodies moved by our own integrator, and Rapier
+/// only couples them to solids.
+#[derive(Resource, Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum IntegratorMode {
+    #[default]
+    Rapier,
+    SymplecticEuler,
+    /// Kick-drift-kick leapfrog (velocity Verlet)
+    Leapfrog,
+}
+
+#[derive(Component, Clone, Copy, Debug, Default)]
+pub struct Position(pub Vec2);
+
+#[derive(Component, Clone, Copy, Debug, Default)]
+pub struct LinearVelocity(pub Vec2);
+
+/// Acceleration from the previous step.
+#[derive(Component, Clone, Copy, Debug, Default)]
+pub struct Acceleration(pub Vec2);
+
+/// Leapfrog's half step velocity v_{n+1/2}, the dt it was kicked with, and
+/// the v_{n+1} it predicted, kept so the next step completes the kick with
+/// the right dt. Empty before the first step, and ignored once something else
+/// has set the velocity.
+#[derive(Component, Clone, Copy, Debug, Default)]
+pub struct HalfStep(pub Option<HalfStepState>);
+
+#[derive(Clone, Copy, Debug)]
+pub struct HalfStepState {
+    pub velocity: Vec2,
+    pub dt: f32,
+    pub predicted: Vec2,
+}
+
+/// SPH force accumulated over the force systems each step.
+#[derive(Component, Clone, Copy, Debug, Default)]
+pub struct Force(pub Vec2);
+
+/// Uniform body acceleration, needed for species of different densities to
+/// stratify.
+#[derive(Resource, Clone, Copy, Debug, Default)]
+pub struct Gravity(pub Vec2);
+
+/// Container walls for the builtin integrators, already shrunk by the
+/// particle radius.
+#[derive(Resource, Clone, Copy, Debug)]
+pub struct Bounds {
+    pub min: Vec2,
+    pub max: Vec2,
+    pub restitution: f32,
+}
+
+impl Bounds {
+    pub fn new(width: f32, height: f32, particle_radius: f32) -> Self {
+        let half_extents = Vec2::new(width, height) / 2.0 - Vec2::splat(particle_radius);
+        Bounds {
+            min: -half_extents,
+            max: half_extents,
+            restitution: 1.0,
+        }
+    }
+
+    pub fn clamp(&self, position: Vec2) -> Vec2 {
+        position.clamp(self.min, self.max)
+    }
+
+    pub fn resolve(&self, position: &mut Vec2, velocity: &mut Vec2) {
+        if position.x < self.min.x {
+            position.x = self.min.x;
+            velocity.x = velocity.x.abs() * self.restitution;
+        } else if position.x > self.max.x {
+            position.x = self.max.x;
+            velocity.x = -velocity.x.abs() * self.restitution;
+        }
+
+        if position.y < self.min.y {
+            position.y = self.min.y;
+            velocity.y = velocity.y.abs() * self.restitution;
+        } else if position.y > self.max.y {
+            position.y = self.max.y;
+            velocity.y = -velocity.y.abs() * self.restitution;
+        }
+    }
+}
+
+/// Whether Rapier integrates the particles, rather than one of our integrators
+/// or a position-level pressure solver.
+pub fn is_rapier_driven(mode: IntegratorMode, solver: PressureSolver) -> bool {
+    mode == IntegratorMode::Rapier && solver == PressureSolver::Wcsph
+}
+
+pub fn rapier_driven(mode: Res<IntegratorMode>, solver: Res<PressureSolver>) -> bool {
+    is_rapier_driven(*mode, *solver)
+}
+
+pub fn builtin_integration(mode: Res<IntegratorMode>, solver: Res<PressureSolver>) -> bool {
+    *mode != IntegratorMode::Rapier && *solver == PressureSolver::Wcsph
+}
+
+pub fn reset_particle_forces(mut query: Query<&mut Force, With<Particle>>) {
+    for mut force in query.iter_mut() {
+        force.0 = Vec2::ZERO;
+    }
+}
+
+pub fn apply_gravity(gravity: Res<Gravity>, mut query: Query<(&mut Force, &Species), With<Particle>>) {
+    for (mut force, species) in query.iter_mut() {
+        force.0 += species.mass * gravity.0;
+    }
+}
+
+pub fn sync_from_rapier(mut query: Query<(&mut Position, &mut LinearVelocity, &Transform, &Velocity), With<Particle>>) {
+    for (mut position, mut linear_velocity, transform, velocity) in query.iter_mut() {
+        position.0 = transform.translation.truncate();
+        linear_velocity.0 = velocity.linvel;
+    }
+}
+
+pub fn apply_rapier_forces(mut query: Query<(&mut ExternalForce, &Force), With<Particle>>) {
+    for (mut external_force, force) in query.iter_mut() {
+        external_force.force = force.0;
+    }
+}
+
+pub fn integrate_particles(timestep: Res<TimeStep>,
+                           mode: Res<IntegratorMode>,
+                           bounds: Res<Bounds>,
+                           mut query: Query<(&mut Position,
+                                             &mut LinearVelocity,
+                                             &mut Acceleration,
+                                             &mut Transform,
+                                             &mut HalfStep,
+                                             &Force,
+                                             &Species), With<Particle>>) {
+    let dt = timestep.dt;
+    let mode = *mode;
+
+    query.par_iter_mut().for_each(|(mut position, mut velocity, mut acceleration, mut transform, mut half_step, force, species)| {
+        let current = force.0 / species.mass;
+
+        match mode {
+            IntegratorMode::Rapier => return,
+            IntegratorMode::SymplecticEuler => {
+                velocity.0 += current * dt;
+                position.0 += velocity.0 * dt;
+                bounds.resolve(&mut position.0, &mut velocity.0);
+            }
+            IntegratorMode::Leapfrog => {
+                // Complete last step's kick with the dt it was made with,
+                // v_n = v_{n-1/2} + dt_{n-1} / 2 a_n, unless the velocity was
+                // changed since, then kick and drift
+                let start = match half_step.0 {
+                    Some(state) if state.predicted == velocity.0 => state.velocity + 0.5 * state.dt * current,
+                    _ => velocity.0,
+                };
+                let mut half = start + 0.5 * dt * current;
+                position.0 += half * dt;
+                bounds.resolve(&mut position.0, &mut half);
+
+                // The velocity dependent forces of the next step see v_{n+1}
+                // predicted with a_n
+                velocity.0 = half + 0.5 * dt * current;
+                half_step.0 = Some(HalfStepState { velocity: half, dt, predicted: velocity.0 });
+            }
+        }
+        acceleration.0 = current;
+
+        transform.translation.x = position.0.x;
+        transform.translation.y = position.0.y;
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::ecs::system::RunSystemOnce;
+    use bevy::tasks::{ComputeTaskPool, TaskPool};
+
+    // Kick-drift-kick is exact under a constant acceleration, whatever the
+    // sequence of step sizes
+    #[test]
+    fn leapfrog_is_exact_under_gravity_with_varying_dt() {
+        ComputeTaskPool::get_or_init(TaskPool::default);
+        let gravity = Vec2::new(0.0, -100.0);
+        let mut world = World::new();
+        world.insert_resource(IntegratorMode::Leapfrog);
+        world.insert_resource(Bounds::new(1e6, 1e6, 0.0));
+        let species = Species { mass: 2.0, ..default() };
+        let particle = world.spawn((Particle,
+                                    Position::default(),
+                                    LinearVelocity::default(),
+                                    Acceleration::default(),
+                                    HalfStep::default(),
+                                    Transform::default(),
+                                    Force(species.mass * gravity),
+                                    species)).id();
+
+        let mut time = 0.0;
+        for dt in [0.01, 0.004, 0.016, 0.0025, 0.01] {
+            world.insert_resource(TimeStep { dt, ..default() });
+            world.run_system_once(integrate_particles);
+            time += dt;
+        }
+
+        let position = world.get::<Position>(particle).unwrap().0;
+        let velocity = world.get::<LinearVelocity>(particle).unwrap().0;
+        assert!(velocity.distance(gravity * time) < 1e-4, "velocity {velocity}, expected {}", gravity * time);
+        let expected = 0.5 * gravity * time * time;
+        assert!(position.distance(expected) < 1e-4, "position {position}, expected {expected}");
+    }
+}
@@ -0,0 +1,489 @@
+use std::f32::consts::PI;
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::INFLUENCE_RADIUS;
+
+/// Radially symmetric 2D smoothing kernel with compact support `radius()`.
+pub trait Kernel: Send + Sync {
+    fn radius(&self) -> f32;
+
+    /// W(r)
+    fn value(&self, r: f32) -> f32;
+
+    /// dW/dr
+    fn derivative(&self, r: f32) -> f32;
+
+    /// ∇²W(r)
+    fn laplacian(&self, r: f32) -> f32;
+
+    /// ∇W at the offset `r = x_i - x_j`
+    fn gradient(&self, r: Vec2) -> Vec2 {
+        let distance = r.length();
+        if distance <= f32::EPSILON || distance >= self.radius() {
+            return Vec2::ZERO;
+        }
+        r / distance * self.derivative(distance)
+    }
+}
+
+/// Müller et al. poly6, W = 4 / (πh⁸) (h² - r²)³
+pub struct Poly6 {
+    h: f32,
+    sigma: f32,
+}
+
+impl Poly6 {
+    pub fn new(radius: f32) -> Self {
+        Poly6 {
+            h: radius,
+            sigma: 4.0 / (PI * radius.powi(8)),
+        }
+    }
+}
+
+impl Kernel for Poly6 {
+    fn radius(&self) -> f32 {
+        self.h
+    }
+
+    fn value(&self, r: f32) -> f32 {
+        if r >= self.h {
+            return 0.0;
+        }
+        self.sigma * (self.h.powi(2) - r.powi(2)).powi(3)
+    }
+
+    fn derivative(&self, r: f32) -> f32 {
+        if r >= self.h {
+            return 0.0;
+        }
+        -6.0 * self.sigma * r * (self.h.powi(2) - r.powi(2)).powi(2)
+    }
+
+    fn laplacian(&self, r: f32) -> f32 {
+        if r >= self.h {
+            return 0.0;
+        }
+        let difference = self.h.powi(2) - r.powi(2);
+        -12.0 * self.sigma * difference * (self.h.powi(2) - 3.0 * r.powi(2))
+    }
+}
+
+/// Müller et al. spiky, W = 10 / (πh⁵) (h - r)³. Its gradient does not vanish
+/// at r = 0, so close particles still push each other apart.
+pub struct Spiky {
+    h: f32,
+    sigma: f32,
+}
+
+impl Spiky {
+    pub fn new(radius: f32) -> Self {
+        Spiky {
+            h: radius,
+            sigma: 10.0 / (PI * radius.powi(5)),
+        }
+    }
+}
+
+impl Kernel for Spiky {
+    fn radius(&self) -> f32 {
+        self.h
+    }
+
+    fn value(&self, r: f32) -> f32 {
+        if r >= self.h {
+            return 0.0;
+        }
+        self.sigma * (self.h - r).powi(3)
+    }
+
+    fn derivative(&self, r: f32) -> f32 {
+        if r >= self.h {
+            return 0.0;
+        }
+        -3.0 * self.sigma * (self.h - r).powi(2)
+    }
+
+    fn laplacian(&self, r: f32) -> f32 {
+        if r >= self.h || r <= f32::EPSILON {
+            return 0.0;
+        }
+        self.sigma * (6.0 * (self.h - r) - 3.0 * (self.h - r).powi(2) / r)
+    }
+}
+
+/// Müller et al. viscosity kernel. W is singular at r = 0 so it is only meant
+/// for its laplacian, which uses the usual 2D form 40 / (πh⁵) (h - r).
+pub struct Viscosity {
+    h: f32,
+    sigma: f32,
+}
+
+impl Viscosity {
+    pub fn new(radius: f32) -> Self {
+        Viscosity {
+            h: radius,
+            sigma: 10.0 / (3.0 * PI * radius.powi(2)),
+        }
+    }
+}
+
+impl Kernel for Viscosity {
+    fn radius(&self) -> f32 {
+        self.h
+    }
+
+    fn value(&self, r: f32) -> f32 {
+        if r >= self.h {
+            return 0.0;
+        }
+        let h = self.h;
+        self.sigma * (-r.powi(3) / (2.0 * h.powi(3)) + r.powi(2) / h.powi(2) + h / (2.0 * r) - 1.0)
+    }
+
+    fn derivative(&self, r: f32) -> f32 {
+        if r >= self.h {
+            return 0.0;
+        }
+        let h = self.h;
+        self.sigma * (-3.0 * r.powi(2) / (2.0 * h.powi(3)) + 2.0 * r / h.powi(2) - h / (2.0 * r.powi(2)))
+    }
+
+    fn laplacian(&self, r: f32) -> f32 {
+        if r >= self.h {
+            return 0.0;
+        }
+        40.0 / (PI * self.h.powi(5)) * (self.h - r)
+    }
+}
+
+/// Monaghan cubic B-spline with smoothing length h / 2, so that its support
+/// matches the other kernels.
+pub struct CubicSpline {
+    h: f32,
+    sigma: f32,
+}
+
+impl CubicSpline {
+    pub fn new(radius: f32) -> Self {
+        CubicSpline {
+            h: radius,
+            sigma: 40.0 / (7.0 * PI * radius.powi(2)),
+        }
+    }
+}
+
+impl Kernel for CubicSpline {
+    fn radius(&self) -> f32 {
+        self.h
+    }
+
+    fn value(&self, r: f32) -> f32 {
+        let q = 2.0 * r / self.h;
+        if q < 1.0 {
+            self.sigma * (1.0 - 1.5 * q.powi(2) + 0.75 * q.powi(3))
+        } else if q < 2.0 {
+            self.sigma * 0.25 * (2.0 - q).powi(3)
+        } else {
+            0.0
+        }
+    }
+
+    fn derivative(&self, r: f32) -> f32 {
+        let q = 2.0 * r / self.h;
+        let dq = 2.0 / self.h;
+        if q < 1.0 {
+            self.sigma * (-3.0 * q + 2.25 * q.powi(2)) * dq
+        } else if q < 2.0 {
+            self.sigma * -0.75 * (2.0 - q).powi(2) * dq
+        } else {
+            0.0
+        }
+    }
+
+    fn laplacian(&self, r: f32) -> f32 {
+        let q = 2.0 * r / self.h;
+        let dq2 = 4.0 / self.h.powi(2);
+        if q < 1.0 {
+            self.sigma * (-6.0 + 6.75 * q) * dq2
+        } else if q < 2.0 {
+            self.sigma * (1.5 * (2.0 - q) - 0.75 * (2.0 - q).powi(2) / q) * dq2
+        } else {
+            0.0
+        }
+    }
+}
+
+/// Wendland C2, W = 7 / (πh²) (1 - q)⁴ (1 + 4q) with q = r / h
+pub struct Wendland {
+    h: f32,
+    sigma: f32,
+}
+
+impl Wendland {
+    pub fn new(radius: f32) -> Self {
+        Wendland {
+            h: radius,
+            sigma: 7.0 / (PI * radius.powi(2)),
+        }
+    }
+}
+
+impl Kernel for Wendland {
+    fn radius(&self) -> f32 {
+        self.h
+    }
+
+    fn value(&self, r: f32) -> f32 {
+        let q = r / self.h;
+        if q >= 1.0 {
+            return 0.0;
+        }
+        self.sigma * (1.0 - q).powi(4) * (1.0 + 4.0 * q)
+    }
+
+    fn derivative(&self, r: f32) -> f32 {
+        let q = r / self.h;
+        if q >= 1.0 {
+            return 0.0;
+        }
+        self.sigma * -20.0 * q * (1.0 - q).powi(3) / self.h
+    }
+
+    fn laplacian(&self, r: f32) -> f32 {
+        let q = r / self.h;
+        if q >= 1.0 {
+            return 0.0;
+        }
+        self.sigma * 20.0 * (1.0 - q).powi(2) * (5.0 * q - 2.0) / self.h.powi(2)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum KernelType {
+    Poly6,
+    Spiky,
+    Viscosity,
+    CubicSpline,
+    Wendland,
+}
+
+impl KernelType {
+    pub fn build(self, radius: f32) -> Box<dyn Kernel> {
+        match self {
+            KernelType::Poly6 => Box::new(Poly6::new(radius)),
+            KernelType::Spiky => Box::new(Spiky::new(radius)),
+            KernelType::Viscosity => Box::new(Viscosity::new(radius)),
+            KernelType::CubicSpline => Box::new(CubicSpline::new(radius)),
+            KernelType::Wendland => Box::new(Wendland::new(radius)),
+        }
+    }
+}
+
+/// The kernel used by each stage: W for density, ∇W for pressure and ∇²W
+/// for viscosity.
+#[derive(Resource)]
+pub struct Kernels {
+    pub density: Box<dyn Kernel>,
+    pub pressure: Box<dyn Kernel>,
+    pub viscosity: Box<dyn Kernel>,
+}
+
+impl Kernels {
+    pub fn new(radius: f32, density: KernelType, pressure: KernelType, viscosity: KernelType) -> Self {
+        Kernels {
+            density: density.build(radius),
+            pressure: pressure.build(radius),
+            viscosity: viscosity.build(radius),
+        }
+    }
+}
+
+impl Default for Kernels {
+    fn default() -> Self {
+        KernelTypes::default().build(INFLUENCE_RADIUS)
+    }
+}
+
+/// Which kernel each stage uses, as chosen in a scene file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct KernelTypes {
+    pub density: KernelType,
+    pub pressure: KernelType,
+    pub viscosity: KernelType,
+}
+
+impl Default for KernelTypes {
+    fn default() -> Self {
+        KernelTypes {
+            density: KernelType::Poly6,
+            pressure: KernelType::Spiky,
+            viscosity: KernelType::Viscosity,
+        }
+    }
+}
+
+impl KernelTypes {
+    pub fn build(&self, radius: f32) -> Kernels {
+        Kernels::new(radius, self.density, self.pressure, self.viscosity)
+    }
+}
+
+/// Integrates W over its disk of support with the midpoint rule in r.
+/// A correctly normalized kernel gives 1.
+pub fn integrate(kernel: &dyn Kernel) -> f32 {
+    let samples = 4096;
+    let dr = kernel.radius() as f64 / samples as f64;
+
+    let integral: f64 = (0..samples)
+        .map(|i| {
+            let r = (i as f64 + 0.5) * dr;
+            2.0 * std::f64::consts::PI * r * kernel.value(r as f32) as f64 * dr
+        })
+        .sum();
+
+    integral as f32
+}
+
+pub fn check_kernel_normalization(kernels: Res<Kernels>) {
+    let stages = [
+        ("density", &kernels.density),
+        ("pressure", &kernels.pressure),
+        ("viscosity", &kernels.viscosity),
+    ];
+
+    for (stage, kernel) in stages {
+        let integral = integrate(kernel.as_ref());
+        if (integral - 1.0).abs() > 1e-2 {
+            warn!("{} kernel integrates to {} over its support, expected 1", stage, integral);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ALL_KERNELS: [KernelType; 5] = [
+        KernelType::Poly6,
+        KernelType::Spiky,
+        KernelType::Viscosity,
+        KernelType::CubicSpline,
+        KernelType::Wendland,
+    ];
+    const RADII: [f32; 3] = [1.0, 20.0, 75.0];
+
+    // Midpoint rule on a cartesian grid covering the disk
+    fn integrate_over_disk(kernel: &dyn Kernel, samples: usize) -> f64 {
+        let h = kernel.radius() as f64;
+        let step = 2.0 * h / samples as f64;
+        let mut integral = 0.0;
+
+        for i in 0..samples {
+            for j in 0..samples {
+                let x = -h + (i as f64 + 0.5) * step;
+                let y = -h + (j as f64 + 0.5) * step;
+                let r = (x * x + y * y).sqrt();
+                if r < h {
+                    integral += kernel.value(r as f32) as f64 * step * step;
+                }
+            }
+        }
+
+        integral
+    }
+
+    fn sample_offsets(radius: f32) -> Vec<Vec2> {
+        (1..40)
+            .map(|i| {
+                let angle = i as f32 * 0.7;
+                let r = radius * i as f32 / 40.0;
+                Vec2::new(angle.cos(), angle.sin()) * r
+            })
+            .collect()
+    }
+
+    #[test]
+    fn kernels_integrate_to_one_over_the_disk() {
+        for kernel_type in ALL_KERNELS {
+            for radius in RADII {
+                let kernel = kernel_type.build(radius);
+                let integral = integrate_over_disk(kernel.as_ref(), 800);
+                assert!((integral - 1.0).abs() < 1e-2,
+                        "{:?} with h = {} integrates to {}", kernel_type, radius, integral);
+            }
+        }
+    }
+
+    #[test]
+    fn radial_integration_matches_normalization() {
+        for kernel_type in ALL_KERNELS {
+            for radius in RADII {
+                let integral = integrate(kernel_type.build(radius).as_ref());
+                assert!((integral - 1.0).abs() < 1e-3,
+                        "{:?} with h = {} integrates to {}", kernel_type, radius, integral);
+            }
+        }
+    }
+
+    #[test]
+    fn kernels_have_compact_support() {
+        for kernel_type in ALL_KERNELS {
+            let kernel = kernel_type.build(75.0);
+            for r in [75.0, 75.5, 100.0, 1000.0] {
+                assert_eq!(kernel.value(r), 0.0, "{:?} value at r = {}", kernel_type, r);
+                assert_eq!(kernel.derivative(r), 0.0, "{:?} derivative at r = {}", kernel_type, r);
+                assert_eq!(kernel.laplacian(r), 0.0, "{:?} laplacian at r = {}", kernel_type, r);
+                assert_eq!(kernel.gradient(Vec2::new(r, 0.0)), Vec2::ZERO);
+            }
+        }
+    }
+
+    #[test]
+    fn kernels_are_radially_symmetric() {
+        for kernel_type in ALL_KERNELS {
+            let kernel = kernel_type.build(75.0);
+            for offset in sample_offsets(75.0) {
+                let gradient = kernel.gradient(offset);
+                assert_eq!(gradient, -kernel.gradient(-offset), "{:?} at {}", kernel_type, offset);
+
+                let rotated = kernel.gradient(offset.perp());
+                assert!((rotated.length() - gradient.length()).abs() <= 1e-6 * gradient.length().max(1e-12),
+                        "{:?} at {}", kernel_type, offset);
+            }
+        }
+    }
+
+    #[test]
+    fn gradients_point_towards_the_neighbour() {
+        for kernel_type in ALL_KERNELS {
+            let kernel = kernel_type.build(75.0);
+            for offset in sample_offsets(75.0) {
+                assert!(kernel.derivative(offset.length()) <= 0.0, "{:?} at {}", kernel_type, offset);
+                assert!(kernel.gradient(offset).dot(offset) <= 0.0, "{:?} at {}", kernel_type, offset);
+            }
+        }
+    }
+
+    #[test]
+    fn derivative_matches_finite_difference() {
+        for kernel_type in ALL_KERNELS {
+            let kernel = kernel_type.build(75.0);
+            let distances: Vec<f32> = (1..20).map(|i| 75.0 * i as f32 / 20.0).collect();
+            let peak = distances
+                .iter()
+                .map(|r| kernel.derivative(*r).abs())
+                .fold(0.0, f32::max);
+
+            for r in distances {
+                let step = 1e-2;
+                let expected = (kernel.value(r + step) - kernel.value(r - step)) / (2.0 * step);
+                assert!((kernel.derivative(r) - expected).abs() <= 1e-3 * peak,
+                        "{:?} at r = {}: {} vs {}", kernel_type, r, kernel.derivative(r), expected);
+            }
+        }
+    }
+}
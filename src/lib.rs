@@ -0,0 +1,52 @@
+//! Smoothed particle hydrodynamics on Bevy and Rapier, with multiphase
+//! species, heat, reactions and electrostatics. Add `FluidSimPlugin` to an
+//! app to embed the simulation.
+
+use bevy::prelude::*;
+
+pub mod barnes_hut;
+pub mod cell;
+pub mod config;
+pub mod density;
+pub mod dfsph;
+pub mod electrostatics;
+pub mod headless;
+pub mod heat;
+pub mod iisph;
+pub mod integrator;
+pub mod kernel;
+pub mod pbf;
+pub mod phase;
+pub mod plugin;
+pub mod pressure;
+pub mod reaction;
+pub mod rng;
+pub mod scene;
+pub mod snapshot;
+pub mod solute;
+pub mod spatial_hash;
+pub mod species;
+pub mod surface_tension;
+pub mod timestep;
+pub mod viscosity;
+
+pub use cell::Cell;
+pub use config::SimConfig;
+pub use plugin::{FluidSimPlugin, FluidSimSet};
+
+/// Defaults, overridden by the scene's `SimConfig`
+pub const INFLUENCE_RADIUS: f32 = 75.0;
+pub const PARTICLE_MASS: f32 = 1.0;
+
+#[derive(Component)]
+pub struct Density {
+    pub value: f32,
+}
+
+#[derive(Component)]
+pub struct Pressure {
+    pub value: f32,
+}
+
+#[derive(Component)]
+pub struct Particle;
@@ -0,0 +1,231 @@
+use bevy::prelude::*;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::integrator::{Acceleration, Bounds, Force, LinearVelocity, Position};
+use crate::kernel::Kernels;
+use crate::spatial_hash::SpatialHash;
+use crate::species::Species;
+use crate::timestep::TimeStep;
+use crate::{Density, Particle};
+
+/// Macklin & Müller, Position Based Fluids (2013).
+#[derive(Resource, Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct PbfSettings {
+    pub iterations: u32,
+    /// ε added to the λ denominator to soften the constraint
+    pub relaxation: f32,
+    /// Artificial pressure s_corr = -k (W(r) / W(Δq))^n against clumping
+    pub tensile_k: f32,
+    pub tensile_n: i32,
+    /// Δq as a fraction of the kernel radius
+    pub tensile_dq: f32,
+    /// XSPH velocity smoothing c
+    pub xsph: f32,
+}
+
+impl Default for PbfSettings {
+    fn default() -> Self {
+        PbfSettings {
+            iterations: 4,
+            relaxation: 1e-4,
+            tensile_k: 0.1,
+            tensile_n: 4,
+            tensile_dq: 0.2,
+            xsph: 0.01,
+        }
+    }
+}
+
+pub fn pbf_step(mut hash: ResMut<SpatialHash>,
+                kernels: Res<Kernels>,
+                settings: Res<PbfSettings>,
+                bounds: Res<Bounds>,
+                timestep: Res<TimeStep>,
+                mut query: Query<(Entity,
+                                  &mut Position,
+                                  &mut LinearVelocity,
+                                  &mut Acceleration,
+                                  &mut Density,
+                                  &mut Transform,
+                                  &Force,
+                                  &Species), With<Particle>>) {
+    let dt = timestep.dt;
+    if dt <= 0.0 {
+        return;
+    }
+
+    let h = kernels.pressure.radius();
+
+    // Predict positions from the non-pressure forces. Each species keeps its
+    // own rest density, with V_j = m_j / ρ0_j its particles' rest volume.
+    let mut entities = Vec::new();
+    let mut positions = Vec::new();
+    let mut velocities = Vec::new();
+    let mut predicted = Vec::new();
+    let mut masses = Vec::new();
+    let mut rest_densities = Vec::new();
+    for (entity, position, velocity, _, _, _, force, species) in query.iter() {
+        let predicted_velocity = velocity.0 + dt * force.0 / species.mass;
+        entities.push(entity);
+        positions.push(position.0);
+        velocities.push(velocity.0);
+        predicted.push(bounds.clamp(position.0 + dt * predicted_velocity));
+        masses.push(species.mass);
+        rest_densities.push(species.rest_density);
+    }
+    let volumes: Vec<f32> = masses.iter().zip(&rest_densities).map(|(mass, rest)| mass / rest).collect();
+    hash.rebuild(entities.iter().copied().zip(predicted.iter().copied()));
+
+    // Neighbours are found once, at the predicted positions, and kept through
+    // the iterations while `predicted` moves away from the hash
+    let neighbours: Vec<Vec<usize>> = (0..hash.len())
+        .into_par_iter()
+        .map(|i| hash.neighbours(hash.positions[i], h))
+        .collect();
+
+    let tensile_reference = kernels.density.value(settings.tensile_dq * h);
+    let mut densities = vec![0.0; hash.len()];
+
+    for _ in 0..settings.iterations {
+        densities = (0..hash.len())
+            .into_par_iter()
+            .map(|i| {
+                neighbours[i]
+                    .iter()
+                    .map(|&j| masses[j] * kernels.density.value(predicted[i].distance(predicted[j])))
+                    .sum()
+            })
+            .collect();
+
+        // λ_i = -C_i / (sum_k |∇_k C_i|² + ε), C_i = ρ_i / ρ0_i - 1
+        let lambdas: Vec<f32> = (0..hash.len())
+            .into_par_iter()
+            .map(|i| {
+                let constraint = densities[i] / rest_densities[i] - 1.0;
+                let mut gradient_i = Vec2::ZERO;
+                let mut gradient_sum = 0.0;
+
+                for &j in neighbours[i].iter().filter(|&&j| j != i) {
+                    let gradient_j = volumes[j] * kernels.pressure.gradient(predicted[i] - predicted[j]);
+                    gradient_i += gradient_j;
+                    gradient_sum += gradient_j.length_squared();
+                }
+
+                -constraint / (gradient_sum + gradient_i.length_squared() + settings.relaxation)
+            })
+            .collect();
+
+        // Δp_i = sum_j V_j (λ_i + λ_j + s_corr) ∇W(p_i - p_j)
+        let corrections: Vec<Vec2> = (0..hash.len())
+            .into_par_iter()
+            .map(|i| {
+                let mut correction = Vec2::ZERO;
+                for &j in neighbours[i].iter().filter(|&&j| j != i) {
+                    let offset = predicted[i] - predicted[j];
+                    let ratio = kernels.density.value(offset.length()) / tensile_reference;
+                    let tensile = -settings.tensile_k * ratio.powi(settings.tensile_n);
+
+                    correction += (lambdas[i] + lambdas[j] + tensile) * volumes[j]
+                        * kernels.pressure.gradient(offset);
+                }
+                correction
+            })
+            .collect();
+
+        for (position, correction) in predicted.iter_mut().zip(corrections) {
+            *position = bounds.clamp(*position + correction);
+        }
+    }
+
+    let mut new_velocities: Vec<Vec2> = predicted
+        .iter()
+        .zip(&positions)
+        .map(|(predicted, position)| (*predicted - *position) / dt)
+        .collect();
+
+    // XSPH: v_i += c sum_j m_j / ρ_j (v_j - v_i) W(r_ij)
+    if settings.xsph > 0.0 {
+        new_velocities = (0..hash.len())
+            .into_par_iter()
+            .map(|i| {
+                let mut smoothing = Vec2::ZERO;
+                for &j in neighbours[i].iter().filter(|&&j| j != i && densities[j] > 0.0) {
+                    let distance = predicted[i].distance(predicted[j]);
+                    smoothing += masses[j] / densities[j]
+                        * (new_velocities[j] - new_velocities[i])
+                        * kernels.density.value(distance);
+                }
+                new_velocities[i] + settings.xsph * smoothing
+            })
+            .collect();
+    }
+
+    // The reactions later in the step search the hash at the final positions
+    hash.rebuild(entities.iter().copied().zip(predicted.iter().copied()));
+
+    for i in 0..hash.len() {
+        if let Ok((_, mut position, mut velocity, mut acceleration, mut density, mut transform, _, _)) =
+            query.get_mut(hash.entities[i]) {
+            acceleration.0 = (new_velocities[i] - velocities[i]) / dt;
+            position.0 = hash.positions[i];
+            velocity.0 = new_velocities[i];
+            density.value = densities[i];
+            transform.translation.x = position.0.x;
+            transform.translation.y = position.0.y;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::ecs::system::RunSystemOnce;
+
+    use crate::kernel::KernelTypes;
+
+    // The corrections move particles across cells, so the hash left for the
+    // rest of the step must be searched at the corrected positions
+    #[test]
+    fn hash_matches_the_corrected_positions() {
+        let h = 20.0;
+        let mut world = World::new();
+        world.insert_resource(KernelTypes::default().build(h));
+        world.insert_resource(PbfSettings::default());
+        world.insert_resource(Bounds::new(1000.0, 1000.0, 0.0));
+        world.insert_resource(TimeStep { dt: 1.0 / 60.0, ..default() });
+        world.insert_resource(SpatialHash::new(h));
+
+        // A block packed at a third of its rest spacing, which pushes apart
+        for i in 0..400 {
+            let position = 3.0 * Vec2::new((i % 20) as f32, (i / 20) as f32);
+            world.spawn((Particle,
+                         Position(position),
+                         LinearVelocity::default(),
+                         Acceleration::default(),
+                         Density { value: 0.0 },
+                         Transform::default(),
+                         Force::default(),
+                         Species::default()));
+        }
+        world.run_system_once(pbf_step);
+
+        let mut query = world.query::<(Entity, &Position)>();
+        let positions: Vec<(Entity, Vec2)> = query.iter(&world).map(|(entity, position)| (entity, position.0)).collect();
+        let hash = world.resource::<SpatialHash>();
+        for (entity, position) in positions.iter() {
+            let i = hash.entities.iter().position(|other| other == entity).unwrap();
+            assert_eq!(hash.positions[i], *position);
+
+            let mut found: Vec<Entity> = hash.neighbours(*position, h).into_iter().map(|j| hash.entities[j]).collect();
+            let mut expected: Vec<Entity> = positions
+                .iter()
+                .filter(|(_, other)| other.distance_squared(*position) <= h * h)
+                .map(|(other, _)| *other)
+                .collect();
+            found.sort();
+            expected.sort();
+            assert_eq!(found, expected);
+        }
+    }
+}
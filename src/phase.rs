@@ -0,0 +1,305 @@
+use std::collections::HashMap;
+
+use bevy::prelude::*;
+use bevy_rapier2d::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::heat::Temperature;
+use crate::integrator::{Force, LinearVelocity};
+use crate::pressure::EquationOfState;
+use crate::spatial_hash::SpatialHash;
+use crate::species::Species;
+use crate::Particle;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Phase {
+    Solid,
+    Liquid,
+    Gas,
+}
+
+impl Phase {
+    /// Phase of a species at a temperature, ignoring latent heat.
+    pub fn at(species: &Species, temperature: f32) -> Self {
+        if temperature < species.melting_point {
+            Phase::Solid
+        } else if temperature < species.boiling_point {
+            Phase::Liquid
+        } else {
+            Phase::Gas
+        }
+    }
+
+    pub fn tint(&self, colour: Color) -> Color {
+        match self {
+            Phase::Solid => {
+                let [red, green, blue, alpha] = colour.as_rgba_f32();
+                Color::rgba(0.6 * red, 0.6 * green, 0.6 * blue, alpha)
+            }
+            Phase::Liquid => colour,
+            Phase::Gas => colour.with_a(0.4),
+        }
+    }
+}
+
+/// Phase of a particle and the latent heat, per unit mass, absorbed at the
+/// current transition temperature. Positive while heading up to the next
+/// phase and negative while heading down. The temperature stays pinned at the
+/// transition until the full latent heat has been exchanged.
+#[derive(Component, Clone, Copy, Debug, PartialEq)]
+pub struct PhaseState {
+    pub phase: Phase,
+    pub latent: f32,
+}
+
+impl PhaseState {
+    pub fn new(species: &Species, temperature: f32) -> Self {
+        PhaseState {
+            phase: Phase::at(species, temperature),
+            latent: 0.0,
+        }
+    }
+
+    /// Moves heat above or below the current phase's transition temperatures
+    /// into latent heat, changing phase once enough has been exchanged.
+    pub fn update(&mut self, temperature: &mut f32, species: &Species) {
+        let heat_capacity = species.heat_capacity;
+        if heat_capacity <= 0.0 {
+            return;
+        }
+
+        let (up, down) = match self.phase {
+            Phase::Solid => (Some((species.melting_point, species.fusion_heat, Phase::Liquid)), None),
+            Phase::Liquid => (Some((species.boiling_point, species.vaporization_heat, Phase::Gas)),
+                              Some((species.melting_point, species.fusion_heat, Phase::Solid))),
+            Phase::Gas => (None, Some((species.boiling_point, species.vaporization_heat, Phase::Liquid))),
+        };
+
+        if let Some((transition, latent_heat, next)) = up {
+            if *temperature > transition || self.latent > 0.0 {
+                self.latent += heat_capacity * (*temperature - transition);
+                *temperature = transition;
+
+                if self.latent >= latent_heat {
+                    *temperature += (self.latent - latent_heat) / heat_capacity;
+                    *self = PhaseState { phase: next, latent: 0.0 };
+                } else if self.latent < 0.0 {
+                    *temperature += self.latent / heat_capacity;
+                    self.latent = 0.0;
+                }
+                return;
+            }
+        }
+
+        if let Some((transition, latent_heat, previous)) = down {
+            if *temperature < transition || self.latent < 0.0 {
+                self.latent += heat_capacity * (*temperature - transition);
+                *temperature = transition;
+
+                if self.latent <= -latent_heat {
+                    *temperature += (self.latent + latent_heat) / heat_capacity;
+                    *self = PhaseState { phase: previous, latent: 0.0 };
+                } else if self.latent > 0.0 {
+                    *temperature += self.latent / heat_capacity;
+                    self.latent = 0.0;
+                }
+            }
+        }
+    }
+}
+
+#[derive(Resource, Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct PhaseSettings {
+    /// Solid particles of the same species closer than this move as one body
+    pub bond_radius: f32,
+    /// Gas rest density as a fraction of the species' liquid rest density
+    pub gas_density_ratio: f32,
+    /// Scales the equation of state for gases
+    pub gas_stiffness: f32,
+}
+
+impl Default for PhaseSettings {
+    fn default() -> Self {
+        PhaseSettings {
+            bond_radius: 60.0,
+            gas_density_ratio: 0.1,
+            gas_stiffness: 2.0,
+        }
+    }
+}
+
+impl PhaseSettings {
+    /// Gases push out towards their lower rest density and never pull back in.
+    pub fn pressure(&self, eos: &EquationOfState, phase: Phase, density: f32, species: &Species) -> f32 {
+        match phase {
+            Phase::Gas => {
+                let rest_density = self.gas_density_ratio * species.rest_density;
+                (self.gas_stiffness * eos.pressure_at_rest(density, rest_density)).max(0.0)
+            }
+            Phase::Solid | Phase::Liquid => eos.pressure_at_rest(density, species.rest_density),
+        }
+    }
+}
+
+pub fn phase_change_system(mut query: Query<(&mut Temperature, &mut PhaseState, &Species), With<Particle>>) {
+    query.par_iter_mut().for_each(|(mut temperature, mut state, species)| {
+        state.update(&mut temperature.0, species);
+    });
+}
+
+type ClusterQuery<'w, 's> = Query<'w, 's, (&'static PhaseState,
+                                           &'static Species,
+                                           &'static mut Force,
+                                           &'static mut LinearVelocity,
+                                           Option<&'static mut Velocity>), With<Particle>>;
+
+// Rigid fluid (Carlson et al. 2004): solids take part in the SPH force
+// computation like any fluid, then each cluster's velocities and forces are
+// projected onto rigid body motion, so it moves and turns as one piece under
+// the builtin integrators and Rapier. The position based solvers correct
+// positions afterwards and only keep clusters approximately rigid.
+pub fn rigid_cluster_system(hash: Res<SpatialHash>,
+                            settings: Res<PhaseSettings>,
+                            mut query: ClusterQuery) {
+    let mut solids = Vec::with_capacity(hash.len());
+    let mut bodies = Vec::with_capacity(hash.len());
+    for entity in hash.entities.iter() {
+        let (solid, body) = query
+            .get(*entity)
+            .map_or((None, (0.0, Vec2::ZERO, Vec2::ZERO)), |(state, species, force, velocity, _)| {
+                let solid = (state.phase == Phase::Solid).then(|| species.name.clone());
+                (solid, (species.mass, force.0, velocity.0))
+            });
+        solids.push(solid);
+        bodies.push(body);
+    }
+
+    // Union-find over bonded neighbours
+    let mut parents: Vec<usize> = (0..hash.len()).collect();
+    fn root(parents: &mut [usize], mut i: usize) -> usize {
+        while parents[i] != i {
+            parents[i] = parents[parents[i]];
+            i = parents[i];
+        }
+        i
+    }
+
+    for (i, solid) in solids.iter().enumerate() {
+        let Some(name) = solid else {
+            continue;
+        };
+        hash.for_each_neighbour(hash.positions[i], settings.bond_radius, |j| {
+            if j > i && solids[j].as_ref() == Some(name) {
+                let (a, b) = (root(&mut parents, i), root(&mut parents, j));
+                parents[a] = b;
+            }
+        });
+    }
+
+    let mut clusters: HashMap<usize, Vec<usize>> = HashMap::new();
+    for (i, solid) in solids.iter().enumerate() {
+        if solid.is_some() {
+            let cluster = root(&mut parents, i);
+            clusters.entry(cluster).or_default().push(i);
+        }
+    }
+
+    let perpendicular = |r: Vec2| Vec2::new(-r.y, r.x);
+
+    for members in clusters.values().filter(|members| members.len() > 1) {
+        let (mut mass, mut centre, mut momentum, mut force) = (0.0, Vec2::ZERO, Vec2::ZERO, Vec2::ZERO);
+        for &i in members {
+            let (m, f, v) = bodies[i];
+            mass += m;
+            centre += m * hash.positions[i];
+            momentum += m * v;
+            force += f;
+        }
+        if mass <= 0.0 {
+            continue;
+        }
+        centre /= mass;
+        let velocity = momentum / mass;
+
+        let (mut inertia, mut angular_momentum, mut torque) = (0.0, 0.0, 0.0);
+        for &i in members {
+            let (m, f, v) = bodies[i];
+            let r = hash.positions[i] - centre;
+            inertia += m * r.length_squared();
+            angular_momentum += m * r.perp_dot(v);
+            torque += r.perp_dot(f);
+        }
+        let (omega, alpha) = if inertia > 0.0 {
+            (angular_momentum / inertia, torque / inertia)
+        } else {
+            (0.0, 0.0)
+        };
+
+        for &i in members {
+            let (m, _, _) = bodies[i];
+            let r = hash.positions[i] - centre;
+            if let Ok((_, _, mut particle_force, mut particle_velocity, rapier_velocity)) =
+                query.get_mut(hash.entities[i]) {
+                particle_velocity.0 = velocity + omega * perpendicular(r);
+                particle_force.0 = m * (force / mass + alpha * perpendicular(r) - omega.powi(2) * r);
+                if let Some(mut rapier_velocity) = rapier_velocity {
+                    rapier_velocity.linvel = particle_velocity.0;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn latent_heat_pins_the_temperature() {
+        let water = Species::water();
+        let mut state = PhaseState::new(&water, 270.0);
+        assert_eq!(state.phase, Phase::Solid);
+
+        // Warming past the melting point stores heat instead of raising T
+        let mut temperature = water.melting_point + 10.0;
+        state.update(&mut temperature, &water);
+        assert_eq!(state.phase, Phase::Solid);
+        assert_eq!(temperature, water.melting_point);
+        assert!((state.latent - 10.0 * water.heat_capacity).abs() < 1e-3);
+
+        // Enough heat melts it, with the excess raising the temperature
+        let mut temperature = water.melting_point + water.fusion_heat / water.heat_capacity;
+        state.update(&mut temperature, &water);
+        assert_eq!(state.phase, Phase::Liquid);
+        assert!((temperature - water.melting_point - 10.0).abs() < 1e-3);
+        assert_eq!(state.latent, 0.0);
+    }
+
+    #[test]
+    fn cooling_gives_latent_heat_back() {
+        let water = Species::water();
+        let mut state = PhaseState::new(&water, 300.0);
+
+        // Partly freeze, then warm back up before it solidifies
+        let mut temperature = water.melting_point - 5.0;
+        state.update(&mut temperature, &water);
+        assert_eq!(state.phase, Phase::Liquid);
+        assert_eq!(temperature, water.melting_point);
+
+        let mut temperature = water.melting_point + 8.0;
+        state.update(&mut temperature, &water);
+        assert_eq!(state.phase, Phase::Liquid);
+        assert!((temperature - water.melting_point - 3.0).abs() < 1e-3);
+        assert_eq!(state.latent, 0.0);
+
+        // Boiling and condensing are symmetric
+        let mut state = PhaseState::new(&water, 300.0);
+        let mut temperature = water.boiling_point + water.vaporization_heat / water.heat_capacity + 1.0;
+        state.update(&mut temperature, &water);
+        assert_eq!(state.phase, Phase::Gas);
+        let mut temperature = water.boiling_point - water.vaporization_heat / water.heat_capacity - 1.0;
+        state.update(&mut temperature, &water);
+        assert_eq!(state.phase, Phase::Liquid);
+        assert!((temperature - water.boiling_point + 1.0).abs() < 1e-2);
+    }
+}
@@ -0,0 +1,429 @@
+use std::time::Duration;
+
+use bevy::prelude::*;
+use bevy::time::TimeUpdateStrategy;
+use bevy_prototype_lyon::prelude::*;
+use bevy_rapier2d::prelude::*;
+
+use crate::cell::{add_cell_shapes, calculate_density, setup_cells};
+use crate::config::SimConfig;
+use crate::density::calculate_particle_density;
+use crate::dfsph::{DfsphSettings, DfsphStats, dfsph_step};
+use crate::electrostatics::{CoulombAccuracy, coulomb_force_system};
+use crate::headless::{Headless, finish_headless_run};
+use crate::heat::{
+    HeatSettings, calculate_cell_temperature, colour_particles_by_temperature,
+    displaying_cell_temperature, displaying_particle_temperature, heat_conduction_system,
+};
+use crate::iisph::{IisphConvergence, IisphSettings, iisph_step};
+use crate::integrator::{
+    apply_gravity, apply_rapier_forces, builtin_integration, integrate_particles, rapier_driven,
+    reset_particle_forces, sync_from_rapier,
+};
+use crate::kernel::check_kernel_normalization;
+use crate::pbf::{PbfSettings, pbf_step};
+use crate::phase::{PhaseSettings, phase_change_system, rigid_cluster_system};
+use crate::pressure::{PressureSolver, pressure_force_system, update_cell_pressure};
+use crate::reaction::{check_reaction_table, reaction_system};
+use crate::scene::{add_particle_shapes, setup_bounding_box, setup_particles};
+use crate::snapshot::{Snapshot, SnapshotSettings, restore_snapshot, snapshot_keys};
+use crate::solute::{SoluteSettings, solute_diffusion_system};
+use crate::spatial_hash::rebuild_spatial_hash;
+use crate::species::update_species_colour;
+use crate::surface_tension::{SurfaceTensionSettings, surface_tension_force_system};
+use crate::timestep::{SphStep, TimeStep, run_substeps};
+use crate::viscosity::viscosity_force_system;
+
+/// Stages of the simulation, in order. The first five run in `SphStep` once
+/// per substep, and `Visualize` runs in `FixedUpdate` after all substeps, so
+/// an app orders its own systems with e.g.
+/// `app.add_systems(SphStep, my_force.in_set(FluidSimSet::Forces))` or
+/// `.after(FluidSimSet::Density).before(FluidSimSet::Pressure)`.
+#[derive(SystemSet, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum FluidSimSet {
+    /// Spawns the cells, walls and particles, in `Startup`
+    Setup,
+    /// Pulls Rapier's positions and rebuilds the spatial hash
+    NeighbourSearch,
+    Density,
+    /// Clears the forces and adds the WCSPH pressure force
+    Pressure,
+    /// Body forces, viscosity, surface tension and electrostatics, plus heat
+    /// and solute exchange, phase changes and the rigid solid projection
+    Forces,
+    /// Advances velocities and positions, through Rapier or one of the
+    /// pressure projection solvers, then runs the reactions
+    Integrate,
+    /// Draws and colours the particles and cells
+    Visualize,
+}
+
+#[derive(Clone, Debug)]
+struct HeadlessRun {
+    frames: u32,
+    output: String,
+}
+
+/// The whole simulation. Resources the `SimConfig` covers, such as the
+/// kernels, equation of state and species, are always taken from it; other
+/// settings are only initialised if missing, so an app can insert its own
+/// before or after adding the plugin.
+///
+/// ```ignore
+/// App::new()
+///     .add_plugins(DefaultPlugins)
+///     .add_plugins(FluidSimPlugin::new().with_config(SimConfig::load("scene.json")?))
+///     .run();
+/// ```
+#[derive(Clone, Debug)]
+pub struct FluidSimPlugin {
+    config: SimConfig,
+    snapshot: Option<Snapshot>,
+    scene: bool,
+    visuals: bool,
+    snapshot_keys: bool,
+    fixed_dt: Option<f32>,
+    headless: Option<HeadlessRun>,
+}
+
+impl Default for FluidSimPlugin {
+    fn default() -> Self {
+        FluidSimPlugin {
+            config: SimConfig::default(),
+            snapshot: None,
+            scene: true,
+            visuals: true,
+            snapshot_keys: true,
+            fixed_dt: None,
+            headless: None,
+        }
+    }
+}
+
+impl FluidSimPlugin {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_config(mut self, config: SimConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Starts from `snapshot` instead of spawning the scene. Its config
+    /// replaces any given to `with_config`.
+    pub fn with_snapshot(mut self, snapshot: Snapshot) -> Self {
+        self.config = snapshot.config.clone();
+        self.snapshot = Some(snapshot);
+        self
+    }
+
+    /// Spawns no cells, walls or particles, leaving the app to spawn its own
+    /// with `spawn_particle`.
+    pub fn without_scene(mut self) -> Self {
+        self.scene = false;
+        self
+    }
+
+    /// Skips the shapes and colouring, for apps without a renderer.
+    pub fn without_visuals(mut self) -> Self {
+        self.visuals = false;
+        self
+    }
+
+    /// Leaves F5 and F9 to the app instead of saving and loading snapshots.
+    pub fn without_snapshot_keys(mut self) -> Self {
+        self.snapshot_keys = false;
+        self
+    }
+
+    /// Advances exactly one fixed step of `dt` per frame, however long the
+    /// frame took, so a run depends only on its config and frame count.
+    pub fn with_fixed_dt(mut self, dt: f32) -> Self {
+        self.fixed_dt = Some(dt);
+        self
+    }
+
+    /// Steps with a fixed `dt` as fast as the machine allows, then writes the
+    /// final state to `output` after `frames` steps and exits. Implies
+    /// `without_visuals`.
+    pub fn headless(mut self, frames: u32, dt: f32, output: impl Into<String>) -> Self {
+        self.visuals = false;
+        self.headless = Some(HeadlessRun { frames, output: output.into() });
+        self.with_fixed_dt(dt)
+    }
+}
+
+impl Plugin for FluidSimPlugin {
+    fn build(&self, app: &mut App) {
+        if !app.is_plugin_added::<RapierPhysicsPlugin<()>>() {
+            app.add_plugins(RapierPhysicsPlugin::<()>::default().in_fixed_schedule());
+        }
+
+        app.init_resource::<PbfSettings>()
+            .init_resource::<DfsphSettings>()
+            .init_resource::<DfsphStats>()
+            .init_resource::<IisphSettings>()
+            .init_resource::<IisphConvergence>()
+            .init_resource::<HeatSettings>()
+            .init_resource::<PhaseSettings>()
+            .init_resource::<SoluteSettings>()
+            .init_resource::<TimeStep>()
+            .init_resource::<SurfaceTensionSettings>()
+            .init_resource::<CoulombAccuracy>()
+            .init_resource::<SnapshotSettings>();
+        self.config.apply(&mut app.world);
+
+        match self.snapshot.clone() {
+            Some(snapshot) => {
+                let mut snapshot = Some(snapshot);
+                app.add_systems(Startup, (move |world: &mut World| {
+                    if let Some(snapshot) = snapshot.take() {
+                        restore_snapshot(world, snapshot);
+                    }
+                }).in_set(FluidSimSet::Setup));
+            }
+            None if self.scene => {
+                app.add_systems(Startup, (setup_cells, setup_bounding_box, setup_particles).in_set(FluidSimSet::Setup));
+            }
+            None => {}
+        }
+
+        app.add_systems(Startup, (check_kernel_normalization, check_reaction_table))
+            .configure_sets(SphStep, (
+                FluidSimSet::NeighbourSearch,
+                FluidSimSet::Density,
+                FluidSimSet::Pressure,
+                FluidSimSet::Forces,
+                FluidSimSet::Integrate,
+            ).chain())
+            .add_systems(SphStep, (
+                (sync_from_rapier.run_if(rapier_driven),
+                 rebuild_spatial_hash).chain().in_set(FluidSimSet::NeighbourSearch),
+                calculate_particle_density.in_set(FluidSimSet::Density),
+                (reset_particle_forces,
+                 pressure_force_system.run_if(resource_equals(PressureSolver::Wcsph))).chain().in_set(FluidSimSet::Pressure),
+                (apply_gravity,
+                 viscosity_force_system,
+                 surface_tension_force_system,
+                 coulomb_force_system,
+                 heat_conduction_system,
+                 solute_diffusion_system,
+                 phase_change_system,
+                 rigid_cluster_system).chain().in_set(FluidSimSet::Forces),
+                (apply_rapier_forces.run_if(rapier_driven),
+                 integrate_particles.run_if(builtin_integration),
+                 pbf_step.run_if(resource_equals(PressureSolver::Pbf)),
+                 dfsph_step.run_if(resource_equals(PressureSolver::Dfsph)),
+                 iisph_step.run_if(resource_equals(PressureSolver::Iisph)),
+                 reaction_system).chain().in_set(FluidSimSet::Integrate),
+            ))
+            .configure_sets(FixedUpdate, FluidSimSet::Visualize.after(run_substeps))
+            .add_systems(FixedUpdate, run_substeps.before(PhysicsSet::SyncBackend));
+
+        if self.visuals {
+            if !app.is_plugin_added::<ShapePlugin>() {
+                app.add_plugins(ShapePlugin);
+            }
+            app.add_systems(FixedUpdate, (
+                (add_cell_shapes,
+                 calculate_density,
+                 update_cell_pressure,
+                 calculate_cell_temperature.run_if(displaying_cell_temperature)).chain(),
+                (add_particle_shapes,
+                 update_species_colour.run_if(not(displaying_particle_temperature)),
+                 colour_particles_by_temperature.run_if(displaying_particle_temperature)).chain(),
+            ).in_set(FluidSimSet::Visualize));
+        }
+        if self.snapshot_keys {
+            app.add_systems(Update, snapshot_keys.run_if(resource_exists::<Input<KeyCode>>()));
+        }
+
+        if let Some(dt) = self.fixed_dt {
+            app.insert_resource(Time::<Fixed>::from_seconds(dt as f64))
+                .insert_resource(TimeUpdateStrategy::ManualDuration(Duration::from_secs_f32(dt)));
+        }
+        if let Some(run) = &self.headless {
+            app.insert_resource(Headless {
+                frames: run.frames,
+                output: run.output.clone(),
+                frame: 0,
+                error: None,
+            })
+            .add_systems(FixedUpdate, finish_headless_run.after(run_substeps));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::ecs::system::RunSystemOnce;
+
+    use crate::integrator::{Acceleration, IntegratorMode};
+    use crate::phase::Phase;
+    use crate::reaction::Reaction;
+    use crate::scene::spawn_particle;
+    use crate::snapshot::{ParticleState, capture_snapshot};
+    use crate::species::Species;
+    use crate::surface_tension::SurfaceTension;
+    use crate::Particle;
+
+    fn particles(plugin: FluidSimPlugin) -> usize {
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, TransformPlugin, HierarchyPlugin))
+            .add_plugins(plugin.without_visuals());
+        app.update();
+        app.world.query_filtered::<(), With<Particle>>().iter(&app.world).count()
+    }
+
+    #[derive(Resource, Default)]
+    struct Stages(Vec<FluidSimSet>);
+
+    #[test]
+    fn stages_run_in_order() {
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, TransformPlugin, HierarchyPlugin))
+            .add_plugins(FluidSimPlugin::new().with_fixed_dt(1.0 / 60.0).without_visuals())
+            .init_resource::<Stages>();
+        // Added in reverse, so only the set ordering can put them right
+        app.add_systems(FixedUpdate, (|mut stages: ResMut<Stages>| stages.0.push(FluidSimSet::Visualize))
+            .in_set(FluidSimSet::Visualize));
+        for stage in [FluidSimSet::Integrate,
+                      FluidSimSet::Forces,
+                      FluidSimSet::Pressure,
+                      FluidSimSet::Density,
+                      FluidSimSet::NeighbourSearch] {
+            app.add_systems(SphStep, (move |mut stages: ResMut<Stages>| stages.0.push(stage)).in_set(stage));
+        }
+        for _ in 0..4 {
+            app.update();
+        }
+
+        // Rapier drives the default scene, so each fixed update is one substep
+        let stages = &app.world.resource::<Stages>().0;
+        assert!(!stages.is_empty());
+        for frame in stages.chunks(6) {
+            assert_eq!(frame, [FluidSimSet::NeighbourSearch,
+                               FluidSimSet::Density,
+                               FluidSimSet::Pressure,
+                               FluidSimSet::Forces,
+                               FluidSimSet::Integrate,
+                               FluidSimSet::Visualize]);
+        }
+    }
+
+    #[test]
+    fn spawns_the_configured_scene() {
+        let config = SimConfig { n_particles: 30, ..default() };
+        assert_eq!(particles(FluidSimPlugin::new().with_config(config.clone())), 30);
+        assert_eq!(particles(FluidSimPlugin::new().with_config(config).without_scene()), 0);
+    }
+
+    #[test]
+    fn surface_tension_comes_from_the_species() {
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, TransformPlugin, HierarchyPlugin))
+            .add_plugins(FluidSimPlugin::new().with_config(SimConfig { n_particles: 64, ..default() }).without_visuals());
+        app.update();
+
+        let mut query = app.world.query::<(&Species, &SurfaceTension)>();
+        let tensions: Vec<(String, f32)> = query
+            .iter(&app.world)
+            .map(|(species, tension)| (species.name.clone(), tension.coefficient))
+            .collect();
+        assert!(tensions.iter().any(|(name, _)| name == "oil"));
+        for (name, tension) in tensions {
+            let expected = if name == "oil" { Species::oil() } else { Species::water() }.surface_tension;
+            assert_eq!(tension, expected, "{name}");
+        }
+    }
+
+    // Each particle's species, phase and the bits of its state, so that -0.0 and 0.0
+    // differ. Every value must be finite, or two runs that both blew up would
+    // compare equal.
+    fn run(seed: u64, steps: usize) -> Vec<(String, Phase, Vec<u32>)> {
+        let config = SimConfig {
+            n_particles: 64,
+            particle_spacing: 10.0,
+            integrator: IntegratorMode::Leapfrog,
+            seed,
+            reactions: vec![Reaction {
+                reactants: ["water".into(), "oil".into()],
+                products: ["oil".into(), "water".into()],
+                radius: 30.0,
+                rate: 5.0,
+                activation_energy: 0.0,
+                energy: 0.0,
+            }],
+            ..default()
+        };
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, TransformPlugin, HierarchyPlugin))
+            .add_plugins(FluidSimPlugin::new().with_config(config).with_fixed_dt(1.0 / 60.0).without_visuals());
+        for _ in 0..steps {
+            app.update();
+        }
+        capture_snapshot(&mut app.world)
+            .particles
+            .into_iter()
+            .map(|particle| {
+                let values: Vec<f32> = particle.position
+                    .into_iter()
+                    .chain(particle.velocity)
+                    .chain([particle.charge, particle.temperature, particle.latent])
+                    .chain(particle.concentrations)
+                    .collect();
+                assert!(values.iter().all(|value| value.is_finite()), "{values:?}");
+                (particle.species, particle.phase, values.into_iter().map(f32::to_bits).collect())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn same_seed_gives_identical_runs() {
+        assert_eq!(run(42, 30), run(42, 30));
+        assert_ne!(run(42, 30), run(43, 30));
+    }
+
+    #[test]
+    fn gravity_accelerates_every_species_equally() {
+        for solver in [PressureSolver::Wcsph, PressureSolver::Pbf, PressureSolver::Dfsph, PressureSolver::Iisph] {
+            let config = SimConfig {
+                integrator: IntegratorMode::SymplecticEuler,
+                pressure_solver: solver,
+                ..default()
+            };
+            let mut app = App::new();
+            app.add_plugins((MinimalPlugins, TransformPlugin, HierarchyPlugin))
+                .add_plugins(FluidSimPlugin::new().with_config(config.clone()).without_scene().without_visuals());
+            app.update();
+
+            // Far enough apart that gravity is the only force
+            for (species, x) in [(Species::water(), -300.0), (Species::oil(), 300.0)] {
+                let state = ParticleState {
+                    position: [x, 0.0],
+                    velocity: [0.0, 0.0],
+                    species: species.name.clone(),
+                    charge: 0.0,
+                    temperature: config.ambient_temperature,
+                    phase: Phase::Liquid,
+                    latent: 0.0,
+                    concentrations: Vec::new(),
+                };
+                let config = config.clone();
+                app.world.run_system_once(move |mut commands: Commands| {
+                    spawn_particle(&mut commands, &config, false, &state, species.clone());
+                });
+            }
+            app.world.resource_mut::<TimeStep>().dt = 1.0 / 60.0;
+            app.world.run_schedule(SphStep);
+
+            let mut accelerations = app.world.query_filtered::<(&Acceleration, &Species), With<Particle>>();
+            for (acceleration, species) in accelerations.iter(&app.world) {
+                assert!(acceleration.0.distance(config.gravity()) < 1e-3,
+                        "{solver:?} accelerates {} at {}", species.name, acceleration.0);
+            }
+        }
+    }
+}
@@ -0,0 +1,124 @@
+use bevy::prelude::*;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::integrator::Force;
+use crate::kernel::Kernels;
+use crate::phase::{PhaseSettings, PhaseState};
+use crate::spatial_hash::SpatialHash;
+use crate::species::Species;
+use crate::{Cell, Density, Particle, Pressure};
+
+/// How incompressibility is enforced. `Wcsph` applies the equation of state as
+/// a force and leaves integration to `IntegratorMode`; the other solvers
+/// advance the particles themselves.
+#[derive(Resource, Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PressureSolver {
+    #[default]
+    Wcsph,
+    /// Position Based Fluids
+    Pbf,
+    /// Divergence-Free SPH
+    Dfsph,
+    /// Implicit Incompressible SPH
+    Iisph,
+}
+
+/// Maps a density to a pressure.
+#[derive(Resource, Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum EquationOfState {
+    /// p = k(ρ - ρ0)
+    IdealGas { stiffness: f32, rest_density: f32 },
+    /// p = B((ρ / ρ0)^γ - 1)
+    Tait { stiffness: f32, rest_density: f32, gamma: f32 },
+}
+
+impl Default for EquationOfState {
+    fn default() -> Self {
+        EquationOfState::IdealGas {
+            stiffness: 40000.0,
+            rest_density: 3e-4,
+        }
+    }
+}
+
+impl EquationOfState {
+    pub fn pressure(&self, density: f32) -> f32 {
+        self.pressure_at_rest(density, self.rest_density())
+    }
+
+    /// Pressure for a fluid with its own rest density, such as one species
+    /// of a multiphase mixture.
+    pub fn pressure_at_rest(&self, density: f32, rest_density: f32) -> f32 {
+        match *self {
+            EquationOfState::IdealGas { stiffness, .. } => {
+                stiffness * (density - rest_density)
+            }
+            EquationOfState::Tait { stiffness, gamma, .. } => {
+                stiffness * ((density / rest_density).powf(gamma) - 1.0)
+            }
+        }
+    }
+
+    pub fn rest_density(&self) -> f32 {
+        match *self {
+            EquationOfState::IdealGas { rest_density, .. } => rest_density,
+            EquationOfState::Tait { rest_density, .. } => rest_density,
+        }
+    }
+}
+
+pub fn update_cell_pressure(mut cell_query: Query<&mut Cell>, eos: Res<EquationOfState>) {
+    for mut cell in cell_query.iter_mut() {
+        cell.pressure = eos.pressure(cell.density);
+    }
+}
+
+// Symmetric SPH pressure gradient in terms of the number density δ = ρ / m,
+// which stays consistent across interfaces between species:
+// F_i = -sum_j (p_i / δ_i² + p_j / δ_j²) ∇W(x_i - x_j)
+// Gas particles use the phase's lower rest density and stiffer response.
+pub fn pressure_force_system(hash: Res<SpatialHash>,
+                             kernels: Res<Kernels>,
+                             phases: Res<PhaseSettings>,
+                             mut query: Query<(&mut Force, &mut Pressure, &Density, &Species, &PhaseState), With<Particle>>,
+                             eos: Res<EquationOfState>) {
+    let (pressures, number_densities): (Vec<f32>, Vec<f32>) = hash.entities
+        .iter()
+        .map(|entity| {
+            query.get(*entity).map_or((0.0, 0.0), |(_, _, density, species, state)| {
+                (phases.pressure(&eos, state.phase, density.value, species), density.value / species.mass)
+            })
+        })
+        .unzip();
+
+    let forces: Vec<Vec2> = (0..hash.len())
+        .into_par_iter()
+        .map(|i| {
+            let mut force = Vec2::ZERO;
+            if number_densities[i] <= 0.0 {
+                return force;
+            }
+
+            hash.for_each_neighbour(hash.positions[i], kernels.pressure.radius(), |j| {
+                if i == j || number_densities[j] <= 0.0 {
+                    return;
+                }
+
+                let gradient = kernels.pressure.gradient(hash.positions[i] - hash.positions[j]);
+                let shared_pressure = pressures[i] / number_densities[i].powi(2)
+                    + pressures[j] / number_densities[j].powi(2);
+
+                force -= shared_pressure * gradient;
+            });
+            force
+        })
+        .collect();
+
+    for ((entity, value), p) in hash.entities.iter().zip(forces).zip(pressures) {
+        if let Ok((mut force, mut pressure, _, _, _)) = query.get_mut(*entity) {
+            force.0 += value;
+            pressure.value = p;
+        }
+    }
+}
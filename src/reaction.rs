@@ -0,0 +1,290 @@
+use bevy::prelude::*;
+use bevy_rapier2d::prelude::*;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::heat::Temperature;
+use crate::integrator::LinearVelocity;
+use crate::rng::SimRng;
+use crate::spatial_hash::SpatialHash;
+use crate::species::{Species, SpeciesTable};
+use crate::surface_tension::SurfaceTension;
+use crate::timestep::TimeStep;
+use crate::Particle;
+
+/// Molar gas constant, J / (mol K)
+const GAS_CONSTANT: f32 = 8.314;
+
+/// A + B → C + D between two particles within `radius` of each other. Both
+/// particles are converted in place, so A + B → C is written with C twice.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Reaction {
+    pub reactants: [String; 2],
+    pub products: [String; 2],
+    pub radius: f32,
+    /// Arrhenius pre-exponential factor A in k = A exp(-Ea / RT), per second
+    pub rate: f32,
+    /// Ea in J / mol, 0 for a temperature independent rate
+    #[serde(default)]
+    pub activation_energy: f32,
+    /// Kinetic energy released into the pair, pushing the products apart
+    #[serde(default)]
+    pub energy: f32,
+}
+
+impl Reaction {
+    /// Probability that a pair in range reacts within `dt`.
+    pub fn probability(&self, temperature: f32, dt: f32) -> f32 {
+        if temperature <= 0.0 {
+            return 0.0;
+        }
+        let rate = self.rate * (-self.activation_energy / (GAS_CONSTANT * temperature)).exp();
+        1.0 - (-rate * dt).exp()
+    }
+
+    /// Total mass of the reactants and of the products, skipping unknown
+    /// species.
+    pub fn masses(&self, species: &SpeciesTable) -> (f32, f32) {
+        let mass = |names: &[String; 2]| -> f32 {
+            names.iter().filter_map(|name| species.get(name)).map(|species| species.mass).sum()
+        };
+        (mass(&self.reactants), mass(&self.products))
+    }
+
+    pub fn conserves_mass(&self, species: &SpeciesTable) -> bool {
+        let (before, after) = self.masses(species);
+        (before - after).abs() <= 1e-4 * before
+    }
+
+    /// Whether the pair (a, b), in either order, are this reaction's
+    /// reactants. Returns the products in the pair's order.
+    fn products_for(&self, a: &str, b: &str) -> Option<[&str; 2]> {
+        let [first, second] = &self.reactants;
+        if first == a && second == b {
+            Some([&self.products[0], &self.products[1]])
+        } else if first == b && second == a {
+            Some([&self.products[1], &self.products[0]])
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Resource, Clone, Debug, Default, Serialize, Deserialize)]
+pub struct ReactionTable {
+    pub reactions: Vec<Reaction>,
+}
+
+/// Warns about reactions naming unknown species or not conserving mass.
+pub fn check_reaction_table(reactions: Res<ReactionTable>, species: Res<SpeciesTable>) {
+    for reaction in reactions.reactions.iter() {
+        let names = reaction.reactants.iter().chain(reaction.products.iter());
+        let unknown: Vec<&String> = names.filter(|name| species.get(name).is_none()).collect();
+        if !unknown.is_empty() {
+            warn!("Reaction {:?} -> {:?} uses unknown species {:?}",
+                  reaction.reactants, reaction.products, unknown);
+            continue;
+        }
+
+        if !reaction.conserves_mass(&species) {
+            let (before, after) = reaction.masses(&species);
+            warn!("Reaction {:?} -> {:?} does not conserve mass: {} -> {}",
+                  reaction.reactants, reaction.products, before, after);
+        }
+    }
+}
+
+// Each particle takes part in at most one reaction per step, at the mean
+// temperature of the pair. Pairs are visited in hash order from their lower
+// index only, so each pair rolls once per step with exactly
+// `Reaction::probability`, and conversions draw from `SimRng` in a fixed order.
+pub fn reaction_system(hash: Res<SpatialHash>,
+                       reactions: Res<ReactionTable>,
+                       table: Res<SpeciesTable>,
+                       timestep: Res<TimeStep>,
+                       mut rng: ResMut<SimRng>,
+                       mut query: ReactionQuery) {
+    if reactions.reactions.is_empty() || timestep.dt <= 0.0 {
+        return;
+    }
+
+    let (mut names, temperatures): (Vec<Option<String>>, Vec<f32>) = hash.entities
+        .iter()
+        .map(|entity| {
+            query
+                .get(*entity)
+                .map_or((None, 0.0), |(species, _, temperature, ..)| (Some(species.name.clone()), temperature.0))
+        })
+        .unzip();
+    let mut reacted = vec![false; hash.len()];
+
+    for reaction in reactions.reactions.iter() {
+        for i in 0..hash.len() {
+            if reacted[i] {
+                continue;
+            }
+
+            let mut partner = None;
+            hash.for_each_neighbour(hash.positions[i], reaction.radius, |j| {
+                if partner.is_some() || j <= i || reacted[j] {
+                    return;
+                }
+                let (Some(a), Some(b)) = (&names[i], &names[j]) else {
+                    return;
+                };
+                if let Some(products) = reaction.products_for(a, b) {
+                    let temperature = 0.5 * (temperatures[i] + temperatures[j]);
+                    if rng.gen::<f32>() < reaction.probability(temperature, timestep.dt) {
+                        partner = Some((j, [products[0].to_owned(), products[1].to_owned()]));
+                    }
+                }
+            });
+
+            let Some((j, products)) = partner else {
+                continue;
+            };
+            let (Some(first), Some(second)) = (table.get(&products[0]), table.get(&products[1])) else {
+                continue;
+            };
+
+            react(&mut query, [hash.entities[i], hash.entities[j]], [first, second],
+                  hash.positions[i] - hash.positions[j], reaction.energy);
+            reacted[i] = true;
+            reacted[j] = true;
+            names[i] = Some(products[0].clone());
+            names[j] = Some(products[1].clone());
+        }
+    }
+}
+
+type ReactionQuery<'w, 's> = Query<'w, 's, (&'static mut Species,
+                                            &'static mut LinearVelocity,
+                                            &'static Temperature,
+                                            Option<&'static mut Velocity>,
+                                            Option<&'static mut ColliderMassProperties>,
+                                            Option<&'static mut SurfaceTension>), With<Particle>>;
+
+// Swaps both particles to their products, with their masses and surface
+// tensions, then releases `energy` as kinetic
+// energy along the line between them, conserving momentum.
+fn react(query: &mut ReactionQuery, entities: [Entity; 2], products: [&Species; 2], offset: Vec2, energy: f32) {
+    let Ok([(mut species_a, mut velocity_a, _, rapier_a, mass_a, tension_a),
+            (mut species_b, mut velocity_b, _, rapier_b, mass_b, tension_b)]) = query.get_many_mut(entities) else {
+        return;
+    };
+
+    *species_a = products[0].clone();
+    *species_b = products[1].clone();
+    if let Some(mut mass) = mass_a {
+        *mass = ColliderMassProperties::Mass(products[0].mass);
+    }
+    if let Some(mut mass) = mass_b {
+        *mass = ColliderMassProperties::Mass(products[1].mass);
+    }
+    if let Some(mut tension) = tension_a {
+        tension.coefficient = products[0].surface_tension;
+    }
+    if let Some(mut tension) = tension_b {
+        tension.coefficient = products[1].surface_tension;
+    }
+
+    let normal = offset.normalize_or_zero();
+    if energy <= 0.0 || normal == Vec2::ZERO {
+        return;
+    }
+
+    // ½ μ v_n² gains `energy`, with μ the reduced mass and v_n the separating
+    // speed along the normal
+    let (m_a, m_b) = (products[0].mass, products[1].mass);
+    let reduced_mass = m_a * m_b / (m_a + m_b);
+    let separating = (velocity_a.0 - velocity_b.0).dot(normal);
+    let target = (separating.powi(2) + 2.0 * energy / reduced_mass).sqrt();
+    let impulse = reduced_mass * (target - separating) * normal;
+
+    velocity_a.0 += impulse / m_a;
+    velocity_b.0 -= impulse / m_b;
+    if let Some(mut velocity) = rapier_a {
+        velocity.linvel = velocity_a.0;
+    }
+    if let Some(mut velocity) = rapier_b {
+        velocity.linvel = velocity_b.0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn reaction(activation_energy: f32) -> Reaction {
+        Reaction {
+            reactants: ["a".into(), "b".into()],
+            products: ["c".into(), "d".into()],
+            radius: 10.0,
+            rate: 2.0,
+            activation_energy,
+            energy: 0.0,
+        }
+    }
+
+    #[test]
+    fn probability_follows_arrhenius() {
+        let flat = reaction(0.0);
+        assert!((flat.probability(300.0, 0.1) - (1.0 - (-0.2_f32).exp())).abs() < 1e-6);
+        assert_eq!(flat.probability(300.0, 0.1), flat.probability(1000.0, 0.1));
+        assert!(flat.probability(300.0, 100.0) > 0.999);
+
+        let activated = reaction(20000.0);
+        assert!(activated.probability(300.0, 0.1) < activated.probability(600.0, 0.1));
+        assert!(activated.probability(300.0, 0.1) < flat.probability(300.0, 0.1));
+        assert_eq!(activated.probability(0.0, 0.1), 0.0);
+    }
+
+    #[test]
+    fn pairs_convert_at_the_reaction_probability() {
+        use bevy::ecs::system::RunSystemOnce;
+
+        use crate::rng::SimRng;
+
+        let reaction = reaction(0.0);
+        let (dt, steps, pairs) = (0.05, 10, 4000);
+        let mut world = World::new();
+        world.insert_resource(ReactionTable { reactions: vec![reaction.clone()] });
+        world.insert_resource(SpeciesTable {
+            species: ["a", "b", "c", "d"].map(|name| Species { name: name.into(), ..default() }).to_vec(),
+        });
+        world.insert_resource(TimeStep { dt, ..default() });
+        world.insert_resource(SimRng::new(1));
+
+        // Isolated pairs, each within the reaction radius of its partner only
+        let mut particles = Vec::new();
+        for pair in 0..pairs {
+            let centre = 100.0 * Vec2::new((pair % 64) as f32, (pair / 64) as f32);
+            for (name, offset) in [("a", -2.5), ("b", 2.5)] {
+                let position = centre + Vec2::new(offset, 0.0);
+                let species = Species { name: name.into(), ..default() };
+                let entity = world.spawn((Particle, species, LinearVelocity::default(), Temperature(300.0))).id();
+                particles.push((entity, position));
+            }
+        }
+        let mut hash = SpatialHash::new(reaction.radius);
+        hash.rebuild(particles.into_iter());
+        world.insert_resource(hash);
+
+        for _ in 0..steps {
+            world.run_system_once(reaction_system);
+        }
+
+        let converted = world.query::<&Species>().iter(&world).filter(|species| species.name == "c").count();
+        let measured = converted as f32 / pairs as f32;
+        let expected = 1.0 - (1.0 - reaction.probability(300.0, dt)).powi(steps);
+        assert!((measured - expected).abs() < 0.03, "converted {measured}, expected {expected}");
+    }
+
+    #[test]
+    fn products_follow_reactant_order() {
+        let reaction = reaction(0.0);
+        assert_eq!(reaction.products_for("a", "b"), Some(["c", "d"]));
+        assert_eq!(reaction.products_for("b", "a"), Some(["d", "c"]));
+        assert_eq!(reaction.products_for("a", "a"), None);
+    }
+}
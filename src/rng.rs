@@ -0,0 +1,50 @@
+use bevy::prelude::*;
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+
+/// The simulation's only source of randomness, seeded from `SimConfig::seed`
+/// so that runs with the same seed and fixed dt are bit-identical. SplitMix64,
+/// whose whole state is one integer, so snapshots can carry it and a restored
+/// run draws the same numbers as an uninterrupted one.
+#[derive(Resource, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SimRng {
+    state: u64,
+}
+
+impl SimRng {
+    pub fn new(seed: u64) -> Self {
+        SimRng { state: seed }
+    }
+}
+
+impl Default for SimRng {
+    fn default() -> Self {
+        SimRng::new(0)
+    }
+}
+
+impl RngCore for SimRng {
+    fn next_u32(&mut self) -> u32 {
+        (self.next_u64() >> 32) as u32
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E3779B97F4A7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+        z ^ (z >> 31)
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        for chunk in dest.chunks_mut(8) {
+            let bytes = self.next_u64().to_le_bytes();
+            chunk.copy_from_slice(&bytes[..chunk.len()]);
+        }
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
+        self.fill_bytes(dest);
+        Ok(())
+    }
+}
@@ -0,0 +1,181 @@
+use bevy::prelude::*;
+use bevy_prototype_lyon::prelude::*;
+use bevy_rapier2d::prelude::*;
+use rand::Rng;
+
+use crate::config::SimConfig;
+use crate::electrostatics::Charge;
+use crate::heat::{AmbientTemperature, Temperature};
+use crate::integrator::{Acceleration, Force, HalfStep, IntegratorMode, LinearVelocity, Position, is_rapier_driven};
+use crate::phase::PhaseState;
+use crate::pressure::PressureSolver;
+use crate::rng::SimRng;
+use crate::snapshot::ParticleState;
+use crate::solute::{Concentrations, SoluteSettings};
+use crate::species::{Species, SpeciesTable};
+use crate::surface_tension::SurfaceTension;
+use crate::{Density, Particle, Pressure};
+
+/// Fixed Rapier colliders around the window
+#[derive(Component)]
+pub struct Wall;
+
+pub fn setup_bounding_box(mut commands: Commands, config: Res<SimConfig>) {
+    let (width, height) = (config.window_width, config.window_height);
+
+    // Create bounding box
+    commands.spawn(RigidBody::Fixed)
+        .insert(Collider::cuboid(width / 2.0, 10.0))
+        .insert(TransformBundle::from(
+            Transform::from_xyz(0.0, -height / 2.0 - 10.0, 0.0)
+        ))
+        .insert(Restitution::new(1.0))
+        .insert(Wall);
+
+    commands.spawn(RigidBody::Fixed)
+        .insert(Collider::cuboid(width / 2.0, 10.0))
+        .insert(TransformBundle::from(
+            Transform::from_xyz(0.0, height / 2.0 + 10.0, 0.0)
+        ))
+        .insert(Restitution::new(1.0))
+        .insert(Wall);
+
+    commands.spawn(RigidBody::Fixed)
+        .insert(Collider::cuboid(10.0, height / 2.0))
+        .insert(TransformBundle::from(
+            Transform::from_xyz(-width / 2.0 - 10.0, 0.0, 0.0)
+        ))
+        .insert(Restitution::new(1.0))
+        .insert(Wall);
+
+    commands.spawn(RigidBody::Fixed)
+        .insert(Collider::cuboid(10.0, height / 2.0))
+        .insert(TransformBundle::from(
+            Transform::from_xyz(width / 2.0 + 10.0, 0.0, 0.0)
+        ))
+        .insert(Restitution::new(1.0))
+        .insert(Wall);
+}
+
+/// Spawns `n_particles` in a square block centred on the origin.
+pub fn setup_particles(mut commands: Commands, 
+                       config: Res<SimConfig>,
+                       mode: Res<IntegratorMode>,
+                       solver: Res<PressureSolver>,
+                       species: Res<SpeciesTable>,
+                       solutes: Res<SoluteSettings>,
+                       ambient: Res<AmbientTemperature>,
+                       mut rng: ResMut<SimRng>) {
+    let rapier_driven = is_rapier_driven(*mode, *solver);
+    let particle_radius = config.particle_radius;
+    let n_particles = config.n_particles;
+    let particle_spacing = config.particle_spacing;
+    let particles_per_row: usize = (n_particles as f64).sqrt() as usize;
+    let particles_per_column: usize = (n_particles - 1) / particles_per_row + 1;
+    let spacing: f32 = (particle_radius * 2.0) + particle_spacing;
+
+    for i in 0..n_particles {
+        let x = (i % particles_per_row) as f32 * spacing - (particles_per_row as f32 * spacing) / 2.0;
+        let y = (i / particles_per_row) as f32 * spacing - (particles_per_column as f32 * spacing) / 2.0;
+
+        // Start from an even mix of all species and let them separate
+        let particle_species = &species.species[rng.gen_range(0..species.species.len())];
+        let phase = PhaseState::new(particle_species, ambient.0);
+
+        // Dye the left quarter of the block
+        let mut concentrations = vec![0.0; solutes.solutes.len()];
+        if let Some(dye) = solutes.index("dye") {
+            if i % particles_per_row < particles_per_row / 4 {
+                concentrations[dye] = 1.0;
+            }
+        }
+
+        let state = ParticleState {
+            position: [x, y],
+            velocity: config.initial_velocity,
+            species: particle_species.name.clone(),
+            charge: config.charge,
+            temperature: ambient.0,
+            phase: phase.phase,
+            latent: phase.latent,
+            concentrations,
+        };
+        spawn_particle(&mut commands, &config, rapier_driven, &state, particle_species.clone());
+    }
+}
+
+/// Spawns one particle with every component the simulation reads, but no
+/// shape; `add_particle_shapes` draws it when there is a window.
+pub fn spawn_particle(commands: &mut Commands,
+                      config: &SimConfig,
+                      rapier_driven: bool,
+                      state: &ParticleState,
+                      species: Species) -> Entity {
+    // Our own integrators move the particles, so Rapier only sees them as
+    // kinematic bodies pushing on solids
+    let body = if rapier_driven {
+        RigidBody::Dynamic
+    } else {
+        RigidBody::KinematicPositionBased
+    };
+    let position = Vec2::from(state.position);
+    let velocity = Vec2::from(state.velocity);
+    let phase = PhaseState { phase: state.phase, latent: state.latent };
+
+    let g1 = Group::from_bits(0b1000).unwrap();
+    let g2 = Group::from_bits(0b0111).unwrap();
+
+    commands
+        .spawn(Particle)
+        .insert(Density { value: 0.0 })
+        .insert(Pressure { value: 0.0 })
+        .insert(SurfaceTension { coefficient: species.surface_tension })
+        .insert(Charge(state.charge))
+        .insert(Temperature(state.temperature))
+        .insert(phase)
+        .insert(Concentrations(state.concentrations.clone()))
+        .insert(Position(position))
+        .insert(LinearVelocity(velocity))
+        .insert(Acceleration::default())
+        .insert(HalfStep::default())
+        .insert(Force::default())
+        .insert(body)
+        .insert(Collider::ball(config.particle_radius))
+        .insert(ColliderMassProperties::Mass(species.mass))
+        .insert(species)
+        .insert(TransformBundle::from(
+            Transform::from_xyz(position.x, position.y, 0.0)
+        ))
+        .insert(CollisionGroups::new(g1, g2))
+        .insert(GravityScale(0.0))
+        .insert(Velocity::linear(velocity))
+        .insert(ExternalForce {
+            force: Vec2::ZERO.into(),
+            torque: 0.0, 
+        })
+        .id()
+}
+
+// Shapes are only added when there is a window to draw them, so headless runs
+// skip lyon entirely. Runs on every newly spawned entity, including those
+// restored from a snapshot.
+pub fn add_particle_shapes(mut commands: Commands,
+                           config: Res<SimConfig>,
+                           query: Query<(Entity, &Transform, &Species, &PhaseState), Added<Particle>>) {
+    for (entity, transform, species, state) in query.iter() {
+        let shape = shapes::Circle {
+            radius: config.particle_radius,
+            center: Vec2::ZERO,
+        };
+
+        commands.entity(entity).insert((
+            ShapeBundle {
+                path: GeometryBuilder::build_as(&shape),
+                spatial: SpatialBundle::from_transform(*transform),
+                ..default()
+            },
+            Fill::color(state.phase.tint(species.colour)),
+            Stroke::new(Color::BLACK, 1.0),
+        ));
+    }
+}
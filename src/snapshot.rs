@@ -0,0 +1,448 @@
+use std::error::Error;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use bevy::ecs::system::{CommandQueue, RunSystemOnce};
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::config::SimConfig;
+use crate::dfsph::DfsphSettings;
+use crate::electrostatics::{Charge, ElectrostaticSettings};
+use crate::heat::{AmbientTemperature, HeatSettings, Temperature, WallTemperature};
+use crate::iisph::IisphSettings;
+use crate::integrator::{is_rapier_driven, Bounds, Gravity, IntegratorMode, LinearVelocity, Position};
+use crate::pbf::PbfSettings;
+use crate::phase::{Phase, PhaseSettings, PhaseState};
+use crate::pressure::{EquationOfState, PressureSolver};
+use crate::reaction::ReactionTable;
+use crate::rng::SimRng;
+use crate::solute::{Concentrations, SoluteSettings};
+use crate::species::{Species, SpeciesTable};
+use crate::surface_tension::SurfaceTensionSettings;
+use crate::timestep::TimeStep;
+use crate::viscosity::ViscositySettings;
+use crate::cell::setup_cells;
+use crate::scene::{setup_bounding_box, spawn_particle, Wall};
+use crate::{Cell, Particle};
+
+/// Identifies the binary format, followed by a little endian u32 version.
+const MAGIC: &[u8; 8] = b"CHEMSNAP";
+const VERSION: u32 = 1;
+
+/// Everything needed to carry on a run: the scene, which also fixes the cell
+/// grid and the walls, the solver and physics settings, the random number
+/// generator and every particle. Solver statistics are not kept, nor the
+/// acceleration and pressure each particle carries between steps, so the
+/// first adaptive substep, leapfrog's half kick and the IISPH warm start
+/// begin afresh after a restore. Settings missing from older snapshots keep
+/// their defaults.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub config: SimConfig,
+    pub restitution: f32,
+    pub pbf: PbfSettings,
+    pub dfsph: DfsphSettings,
+    pub iisph: IisphSettings,
+    #[serde(default)]
+    pub viscosity: ViscositySettings,
+    #[serde(default)]
+    pub surface_tension: SurfaceTensionSettings,
+    #[serde(default)]
+    pub electrostatics: ElectrostaticSettings,
+    #[serde(default)]
+    pub heat: HeatSettings,
+    #[serde(default)]
+    pub phase: PhaseSettings,
+    #[serde(default)]
+    pub solutes: SoluteSettings,
+    #[serde(default)]
+    pub timestep: TimeStep,
+    #[serde(default)]
+    pub rng: SimRng,
+    pub particles: Vec<ParticleState>,
+}
+
+/// The state of one particle. Density, pressure and forces are recomputed on
+/// the first step.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ParticleState {
+    pub position: [f32; 2],
+    pub velocity: [f32; 2],
+    pub species: String,
+    pub charge: f32,
+    pub temperature: f32,
+    pub phase: Phase,
+    pub latent: f32,
+    pub concentrations: Vec<f32>,
+}
+
+impl Snapshot {
+    /// Files ending in `.bin` use the binary format, anything else JSON.
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), Box<dyn Error>> {
+        let path = path.as_ref();
+        if is_binary(path) {
+            fs::write(path, self.to_bytes()?)?;
+        } else {
+            fs::write(path, serde_json::to_string_pretty(self)?)?;
+        }
+        Ok(())
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, Box<dyn Error>> {
+        let path = path.as_ref();
+        let snapshot: Snapshot = if is_binary(path) {
+            Snapshot::from_bytes(&fs::read(path)?)?
+        } else {
+            serde_json::from_str(&fs::read_to_string(path)?)?
+        };
+        snapshot.config.validate()?;
+        Ok(snapshot)
+    }
+
+    // Header as JSON, then fixed size particle records with the species as an
+    // index into the scene's species table:
+    // position, velocity (4 × f32), species (u16), charge, temperature (f32),
+    // phase (u8), latent heat (f32), one f32 per solute
+    pub fn to_bytes(&self) -> Result<Vec<u8>, Box<dyn Error>> {
+        let header = serde_json::to_vec(&Snapshot { particles: Vec::new(), ..self.clone() })?;
+        let solutes = self.particles.iter().map(|particle| particle.concentrations.len()).max().unwrap_or(0);
+
+        let mut bytes = Vec::with_capacity(header.len() + self.particles.len() * record_size(solutes) + 24);
+        bytes.extend_from_slice(MAGIC);
+        bytes.extend_from_slice(&VERSION.to_le_bytes());
+        bytes.extend_from_slice(&(header.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(&header);
+        bytes.extend_from_slice(&(self.particles.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(&(solutes as u32).to_le_bytes());
+
+        for particle in self.particles.iter() {
+            let species = self.config.species
+                .iter()
+                .position(|species| species.name == particle.species)
+                .ok_or_else(|| invalid(format!("species {} is not in the scene", particle.species)))?;
+
+            for value in particle.position.iter().chain(particle.velocity.iter()) {
+                bytes.extend_from_slice(&value.to_le_bytes());
+            }
+            bytes.extend_from_slice(&(species as u16).to_le_bytes());
+            bytes.extend_from_slice(&particle.charge.to_le_bytes());
+            bytes.extend_from_slice(&particle.temperature.to_le_bytes());
+            bytes.push(match particle.phase {
+                Phase::Solid => 0,
+                Phase::Liquid => 1,
+                Phase::Gas => 2,
+            });
+            bytes.extend_from_slice(&particle.latent.to_le_bytes());
+            for k in 0..solutes {
+                let concentration = particle.concentrations.get(k).copied().unwrap_or(0.0);
+                bytes.extend_from_slice(&concentration.to_le_bytes());
+            }
+        }
+        Ok(bytes)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Box<dyn Error>> {
+        let mut reader = Reader { bytes };
+        if reader.take(MAGIC.len())? != MAGIC {
+            return Err(invalid("not a snapshot".into()).into());
+        }
+        let version = reader.u32()?;
+        if version != VERSION {
+            return Err(invalid(format!("unsupported snapshot version {version}")).into());
+        }
+
+        let header_length = reader.u32()? as usize;
+        let mut snapshot: Snapshot = serde_json::from_slice(reader.take(header_length)?)?;
+        let count = reader.u32()? as usize;
+        let solutes = reader.u32()? as usize;
+        // A corrupt count must not size the allocation
+        let expected = record_size(solutes).checked_mul(count);
+        if expected != Some(reader.bytes.len()) {
+            return Err(invalid(format!("{count} particles do not fit {} bytes of records", reader.bytes.len())).into());
+        }
+
+        snapshot.particles = Vec::with_capacity(count);
+        for _ in 0..count {
+            let position = [reader.f32()?, reader.f32()?];
+            let velocity = [reader.f32()?, reader.f32()?];
+            let species = reader.u16()? as usize;
+            let species = snapshot.config.species
+                .get(species)
+                .ok_or_else(|| invalid(format!("unknown species index {species}")))?
+                .name
+                .clone();
+            let charge = reader.f32()?;
+            let temperature = reader.f32()?;
+            let phase = match reader.take(1)?[0] {
+                0 => Phase::Solid,
+                1 => Phase::Liquid,
+                2 => Phase::Gas,
+                other => return Err(invalid(format!("unknown phase {other}")).into()),
+            };
+            let latent = reader.f32()?;
+            let concentrations = (0..solutes).map(|_| reader.f32()).collect::<Result<_, _>>()?;
+
+            snapshot.particles.push(ParticleState {
+                position,
+                velocity,
+                species,
+                charge,
+                temperature,
+                phase,
+                latent,
+                concentrations,
+            });
+        }
+        Ok(snapshot)
+    }
+}
+
+fn record_size(solutes: usize) -> usize {
+    31 + 4 * solutes
+}
+
+fn is_binary(path: &Path) -> bool {
+    path.extension().is_some_and(|extension| extension == "bin")
+}
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, length: usize) -> io::Result<&'a [u8]> {
+        if self.bytes.len() < length {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        let (taken, rest) = self.bytes.split_at(length);
+        self.bytes = rest;
+        Ok(taken)
+    }
+
+    fn u16(&mut self) -> io::Result<u16> {
+        Ok(u16::from_le_bytes(self.take(2)?.try_into().unwrap()))
+    }
+
+    fn u32(&mut self) -> io::Result<u32> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn f32(&mut self) -> io::Result<f32> {
+        Ok(f32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+}
+
+/// Where F5 saves and F9 loads.
+#[derive(Resource, Clone, Debug)]
+pub struct SnapshotSettings {
+    pub path: String,
+}
+
+impl Default for SnapshotSettings {
+    fn default() -> Self {
+        SnapshotSettings { path: "snapshot.json".into() }
+    }
+}
+
+/// The current state, with the scene updated from any settings changed since
+/// it was loaded.
+pub fn capture_snapshot(world: &mut World) -> Snapshot {
+    let mut config = world.resource::<SimConfig>().clone();
+    config.integrator = *world.resource::<IntegratorMode>();
+    config.pressure_solver = *world.resource::<PressureSolver>();
+    config.gravity = world.resource::<Gravity>().0.into();
+    config.species = world.resource::<SpeciesTable>().species.clone();
+    config.reactions = world.resource::<ReactionTable>().reactions.clone();
+    config.equation_of_state = *world.resource::<EquationOfState>();
+    config.ambient_temperature = world.resource::<AmbientTemperature>().0;
+    config.viscosity = world.resource::<ViscositySettings>().coefficient;
+    config.coulomb_constant = world.resource::<ElectrostaticSettings>().coulomb_constant;
+    config.floor_temperature = match world.resource::<HeatSettings>().bottom {
+        WallTemperature::Fixed(temperature) => Some(temperature),
+        WallTemperature::Insulated => None,
+    };
+
+    let mut query = world.query_filtered::<(&Position,
+                                            &LinearVelocity,
+                                            &Species,
+                                            &Charge,
+                                            &Temperature,
+                                            &PhaseState,
+                                            &Concentrations), With<Particle>>();
+    let particles = query
+        .iter(world)
+        .map(|(position, velocity, species, charge, temperature, state, concentrations)| ParticleState {
+            position: position.0.into(),
+            velocity: velocity.0.into(),
+            species: species.name.clone(),
+            charge: charge.0,
+            temperature: temperature.0,
+            phase: state.phase,
+            latent: state.latent,
+            concentrations: concentrations.0.clone(),
+        })
+        .collect();
+
+    Snapshot {
+        config,
+        restitution: world.resource::<Bounds>().restitution,
+        pbf: *world.resource::<PbfSettings>(),
+        dfsph: *world.resource::<DfsphSettings>(),
+        iisph: *world.resource::<IisphSettings>(),
+        viscosity: *world.resource::<ViscositySettings>(),
+        surface_tension: *world.resource::<SurfaceTensionSettings>(),
+        electrostatics: *world.resource::<ElectrostaticSettings>(),
+        heat: *world.resource::<HeatSettings>(),
+        phase: *world.resource::<PhaseSettings>(),
+        solutes: world.resource::<SoluteSettings>().clone(),
+        timestep: *world.resource::<TimeStep>(),
+        rng: world.resource::<SimRng>().clone(),
+        particles,
+    }
+}
+
+/// Replaces the particles, cells and walls with the snapshot's, and applies
+/// its scene and solver settings.
+pub fn restore_snapshot(world: &mut World, snapshot: Snapshot) {
+    let mut existing = world.query_filtered::<Entity, Or<(With<Particle>, With<Cell>, With<Wall>)>>();
+    let entities: Vec<Entity> = existing.iter(world).collect();
+    for entity in entities {
+        world.despawn(entity);
+    }
+
+    snapshot.config.apply(world);
+    world.resource_mut::<Bounds>().restitution = snapshot.restitution;
+    world.insert_resource(snapshot.pbf);
+    world.insert_resource(snapshot.dfsph);
+    world.insert_resource(snapshot.iisph);
+    world.insert_resource(snapshot.viscosity);
+    world.insert_resource(snapshot.surface_tension);
+    world.insert_resource(snapshot.electrostatics);
+    world.insert_resource(snapshot.heat);
+    world.insert_resource(snapshot.phase);
+    world.insert_resource(snapshot.solutes);
+    world.insert_resource(snapshot.timestep);
+    world.insert_resource(snapshot.rng);
+
+    world.run_system_once(setup_cells);
+    world.run_system_once(setup_bounding_box);
+
+    let rapier_driven = is_rapier_driven(*world.resource::<IntegratorMode>(), *world.resource::<PressureSolver>());
+    let table = world.resource::<SpeciesTable>().clone();
+    let mut queue = CommandQueue::default();
+    let mut commands = Commands::new(&mut queue, world);
+    for particle in snapshot.particles.iter() {
+        match table.get(&particle.species) {
+            Some(species) => {
+                spawn_particle(&mut commands, &snapshot.config, rapier_driven, particle, species.clone());
+            }
+            None => warn!("Skipping particle of unknown species {}", particle.species),
+        }
+    }
+    queue.apply(world);
+}
+
+/// F5 saves a snapshot to `SnapshotSettings::path` and F9 restores it.
+pub fn snapshot_keys(world: &mut World) {
+    let keys = world.resource::<Input<KeyCode>>();
+    let (save, load) = (keys.just_pressed(KeyCode::F5), keys.just_pressed(KeyCode::F9));
+    let path = world.resource::<SnapshotSettings>().path.clone();
+
+    if save {
+        match capture_snapshot(world).save(&path) {
+            Ok(()) => info!("Saved snapshot to {path}"),
+            Err(error) => error!("Could not save snapshot to {path}: {error}"),
+        }
+    }
+    if load {
+        match Snapshot::load(&path) {
+            Ok(snapshot) => {
+                restore_snapshot(world, snapshot);
+                info!("Loaded snapshot from {path}");
+            }
+            Err(error) => error!("Could not load snapshot from {path}: {error}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::RngCore;
+
+    use super::*;
+
+    fn sample() -> Snapshot {
+        let particle = |species: &str, phase| ParticleState {
+            position: [12.5, -40.0],
+            velocity: [3.0, 0.25],
+            species: species.into(),
+            charge: -1.0,
+            temperature: 350.0,
+            phase,
+            latent: 12.0,
+            concentrations: vec![0.5],
+        };
+        Snapshot {
+            config: SimConfig::default(),
+            restitution: 0.8,
+            pbf: PbfSettings::default(),
+            dfsph: DfsphSettings::default(),
+            iisph: IisphSettings::default(),
+            viscosity: ViscositySettings::honey(),
+            surface_tension: SurfaceTensionSettings::default(),
+            electrostatics: ElectrostaticSettings::default(),
+            heat: HeatSettings::default(),
+            phase: PhaseSettings::default(),
+            solutes: SoluteSettings::default(),
+            timestep: TimeStep::default(),
+            rng: SimRng::new(7),
+            particles: vec![particle("water", Phase::Gas), particle("oil", Phase::Solid)],
+        }
+    }
+
+    #[test]
+    fn restore_keeps_settings_and_rng() {
+        let mut snapshot = sample();
+        snapshot.rng.next_u64();
+        let mut world = World::new();
+        restore_snapshot(&mut world, snapshot.clone());
+
+        let restored = capture_snapshot(&mut world);
+        assert_eq!(restored.viscosity.coefficient, ViscositySettings::honey().coefficient);
+        assert_eq!(restored.config.viscosity, ViscositySettings::honey().coefficient);
+        assert_eq!(restored.rng, snapshot.rng);
+        assert_eq!(restored.particles, snapshot.particles);
+        assert_eq!(world.resource_mut::<SimRng>().next_u64(), snapshot.rng.next_u64());
+    }
+
+    #[test]
+    fn binary_round_trips() {
+        let snapshot = sample();
+        let loaded = Snapshot::from_bytes(&snapshot.to_bytes().unwrap()).unwrap();
+        assert_eq!(loaded.particles, snapshot.particles);
+        assert_eq!(loaded.config.species, snapshot.config.species);
+        assert_eq!(loaded.restitution, snapshot.restitution);
+    }
+
+    #[test]
+    fn binary_rejects_truncated_files() {
+        let bytes = sample().to_bytes().unwrap();
+        assert!(Snapshot::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+        assert!(Snapshot::from_bytes(b"not a snapshot").is_err());
+    }
+
+    #[test]
+    fn binary_rejects_corrupt_counts() {
+        let mut bytes = sample().to_bytes().unwrap();
+        let header_length = u32::from_le_bytes(bytes[12..16].try_into().unwrap()) as usize;
+        let count = 16 + header_length;
+        bytes[count..count + 4].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(Snapshot::from_bytes(&bytes).is_err());
+    }
+}
@@ -0,0 +1,169 @@
+use bevy::prelude::*;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::kernel::Kernels;
+use crate::spatial_hash::SpatialHash;
+use crate::species::Species;
+use crate::timestep::TimeStep;
+use crate::{Density, Particle};
+
+/// A dissolved scalar carried by the particles.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Solute {
+    pub name: String,
+    /// Fick diffusivity D in px² / s
+    pub diffusivity: f32,
+}
+
+impl Solute {
+    pub fn dye() -> Self {
+        Solute {
+            name: "dye".into(),
+            diffusivity: 500.0,
+        }
+    }
+}
+
+/// Concentration of each solute in `SoluteSettings::solutes`, in the same
+/// order. The particles move with the fluid, so advection comes for free.
+#[derive(Component, Clone, Debug, Default, PartialEq)]
+pub struct Concentrations(pub Vec<f32>);
+
+#[derive(Resource, Clone, Debug, Serialize, Deserialize)]
+pub struct SoluteSettings {
+    pub solutes: Vec<Solute>,
+    /// Index of the solute shown on the cell grid instead of the density
+    pub display: Option<usize>,
+}
+
+impl Default for SoluteSettings {
+    fn default() -> Self {
+        SoluteSettings {
+            solutes: vec![Solute::dye()],
+            display: None,
+        }
+    }
+}
+
+impl SoluteSettings {
+    pub fn index(&self, name: &str) -> Option<usize> {
+        self.solutes.iter().position(|solute| solute.name == name)
+    }
+
+    pub fn max_diffusivity(&self) -> f32 {
+        self.solutes.iter().map(|solute| solute.diffusivity).fold(0.0, f32::max)
+    }
+}
+
+// Fick's law with the kernel laplacian, symmetrised so the total solute
+// sum_i m_i C_i is conserved across interfaces between species:
+// dC_i/dt = D sum_j 2 m_j / (ρ_i + ρ_j) (C_j - C_i) ∇²W_ij
+pub fn solute_diffusion_system(hash: Res<SpatialHash>,
+                               kernels: Res<Kernels>,
+                               settings: Res<SoluteSettings>,
+                               timestep: Res<TimeStep>,
+                               mut query: Query<(&mut Concentrations, &Density, &Species), With<Particle>>) {
+    let dt = timestep.dt;
+    if dt <= 0.0 || settings.solutes.is_empty() {
+        return;
+    }
+
+    let mut concentrations = Vec::with_capacity(hash.len());
+    let mut densities = Vec::with_capacity(hash.len());
+    let mut masses = Vec::with_capacity(hash.len());
+    for entity in hash.entities.iter() {
+        let (concentration, density, mass) = query
+            .get(*entity)
+            .map_or((Vec::new(), 0.0, 0.0), |(concentrations, density, species)| {
+                (concentrations.0.clone(), density.value, species.mass)
+            });
+        concentrations.push(concentration);
+        densities.push(density);
+        masses.push(mass);
+    }
+
+    let kernel = &kernels.viscosity;
+    let changes: Vec<Vec<f32>> = (0..hash.len())
+        .into_par_iter()
+        .map(|i| {
+            let mut change = vec![0.0; settings.solutes.len()];
+            if densities[i] <= 0.0 {
+                return change;
+            }
+
+            hash.for_each_neighbour(hash.positions[i], kernel.radius(), |j| {
+                if i == j || densities[j] <= 0.0 {
+                    return;
+                }
+                let distance = hash.positions[i].distance(hash.positions[j]);
+                let weight = 2.0 * masses[j] / (densities[i] + densities[j]) * kernel.laplacian(distance);
+
+                for (k, solute) in settings.solutes.iter().enumerate() {
+                    let c_i = concentrations[i].get(k).copied().unwrap_or(0.0);
+                    let c_j = concentrations[j].get(k).copied().unwrap_or(0.0);
+                    change[k] += solute.diffusivity * weight * (c_j - c_i);
+                }
+            });
+            change
+        })
+        .collect();
+
+    for (entity, change) in hash.entities.iter().zip(changes) {
+        if let Ok((mut concentrations, _, _)) = query.get_mut(*entity) {
+            concentrations.0.resize(change.len(), 0.0);
+            for (concentration, rate) in concentrations.0.iter_mut().zip(change) {
+                *concentration += dt * rate;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::ecs::system::RunSystemOnce;
+
+    use crate::kernel::KernelTypes;
+
+    #[test]
+    fn total_solute_is_conserved_across_species() {
+        let h = 75.0;
+        let mut world = World::new();
+        world.insert_resource(KernelTypes::default().build(h));
+        world.insert_resource(SoluteSettings::default());
+        world.insert_resource(TimeStep { dt: 1e-3, ..default() });
+
+        // Dyed water next to clear oil, which differ in density
+        let particles: Vec<(Entity, Vec2)> = [(Species::water(), 1.0, Vec2::ZERO),
+                                              (Species::oil(), 0.0, Vec2::new(10.0, 0.0))]
+            .into_iter()
+            .map(|(species, concentration, position)| {
+                let density = Density { value: species.rest_density };
+                let entity = world.spawn((Particle, Concentrations(vec![concentration]), density, species)).id();
+                (entity, position)
+            })
+            .collect();
+        let mut hash = SpatialHash::new(h);
+        hash.rebuild(particles.iter().copied());
+        world.insert_resource(hash);
+
+        let state = |world: &World| -> Vec<(f32, f32)> {
+            particles
+                .iter()
+                .map(|(entity, _)| (world.get::<Species>(*entity).unwrap().mass,
+                                    world.get::<Concentrations>(*entity).unwrap().0[0]))
+                .collect()
+        };
+        let total = |state: &[(f32, f32)]| -> f32 { state.iter().map(|(mass, concentration)| mass * concentration).sum() };
+
+        let before = state(&world);
+        world.run_system_once(solute_diffusion_system);
+        let after = state(&world);
+
+        assert!(after[0].1 < before[0].1 && after[1].1 > before[1].1, "{before:?} -> {after:?}");
+        assert!(after[0].1 > after[1].1, "{after:?} overshot");
+        assert!((total(&after) - total(&before)).abs() < 1e-6 * total(&before),
+                "{} -> {}", total(&before), total(&after));
+    }
+}
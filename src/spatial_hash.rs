@@ -0,0 +1,130 @@
+use bevy::prelude::*;
+
+use crate::integrator::Position;
+use crate::{Particle, INFLUENCE_RADIUS};
+
+/// Uniform grid over particle positions, hashed into a table sized to the
+/// particle count and rebuilt every frame. Neighbour queries return indices
+/// into `entities` / `positions`, always in the same order, and the parallel
+/// systems sum over them per particle, so no result depends on how rayon
+/// schedules the work.
+#[derive(Resource)]
+pub struct SpatialHash {
+    cell_size: f32,
+    // Start of each bucket in `sorted`, plus a trailing end marker
+    bucket_start: Vec<usize>,
+    sorted: Vec<usize>,
+    cells: Vec<IVec2>,
+    pub entities: Vec<Entity>,
+    pub positions: Vec<Vec2>,
+}
+
+impl Default for SpatialHash {
+    fn default() -> Self {
+        SpatialHash::new(INFLUENCE_RADIUS)
+    }
+}
+
+impl SpatialHash {
+    pub fn new(cell_size: f32) -> Self {
+        SpatialHash {
+            cell_size,
+            bucket_start: vec![0; 2],
+            sorted: Vec::new(),
+            cells: Vec::new(),
+            entities: Vec::new(),
+            positions: Vec::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.positions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.positions.is_empty()
+    }
+
+    fn cell_of(&self, position: Vec2) -> IVec2 {
+        (position / self.cell_size).floor().as_ivec2()
+    }
+
+    fn bucket_of(&self, cell: IVec2) -> usize {
+        let hash = cell.x.wrapping_mul(92837111) ^ cell.y.wrapping_mul(689287499);
+        hash.unsigned_abs() as usize % (self.bucket_start.len() - 1)
+    }
+
+    pub fn rebuild(&mut self, particles: impl Iterator<Item = (Entity, Vec2)>) {
+        self.entities.clear();
+        self.positions.clear();
+        for (entity, position) in particles {
+            self.entities.push(entity);
+            self.positions.push(position);
+        }
+
+        let particle_count = self.positions.len();
+        let table_size = (2 * particle_count).max(1);
+
+        self.bucket_start.clear();
+        self.bucket_start.resize(table_size + 1, 0);
+        self.cells = self.positions.iter().map(|p| self.cell_of(*p)).collect();
+
+        // Counting sort of particle indices by bucket
+        for i in 0..particle_count {
+            let bucket = self.bucket_of(self.cells[i]);
+            self.bucket_start[bucket] += 1;
+        }
+        let mut running = 0;
+        for start in self.bucket_start.iter_mut() {
+            running += *start;
+            *start = running;
+        }
+
+        self.sorted.clear();
+        self.sorted.resize(particle_count, 0);
+        for i in (0..particle_count).rev() {
+            let bucket = self.bucket_of(self.cells[i]);
+            self.bucket_start[bucket] -= 1;
+            self.sorted[self.bucket_start[bucket]] = i;
+        }
+    }
+
+    /// Calls `f` with the index of every particle within `radius` of `point`.
+    pub fn for_each_neighbour(&self, point: Vec2, radius: f32, mut f: impl FnMut(usize)) {
+        if self.is_empty() {
+            return;
+        }
+
+        let min = self.cell_of(point - Vec2::splat(radius));
+        let max = self.cell_of(point + Vec2::splat(radius));
+        let radius_squared = radius * radius;
+
+        for x in min.x..=max.x {
+            for y in min.y..=max.y {
+                let cell = IVec2::new(x, y);
+                let bucket = self.bucket_of(cell);
+
+                for &i in &self.sorted[self.bucket_start[bucket]..self.bucket_start[bucket + 1]] {
+                    // Buckets are shared between colliding cells
+                    if self.cells[i] != cell {
+                        continue;
+                    }
+                    if self.positions[i].distance_squared(point) <= radius_squared {
+                        f(i);
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn neighbours(&self, point: Vec2, radius: f32) -> Vec<usize> {
+        let mut neighbours = Vec::new();
+        self.for_each_neighbour(point, radius, |i| neighbours.push(i));
+        neighbours
+    }
+}
+
+pub fn rebuild_spatial_hash(mut hash: ResMut<SpatialHash>,
+                            query: Query<(Entity, &Position), With<Particle>>) {
+    hash.rebuild(query.iter().map(|(entity, position)| (entity, position.0)));
+}
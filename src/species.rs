@@ -0,0 +1,146 @@
+use bevy::prelude::*;
+use bevy_prototype_lyon::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::phase::PhaseState;
+use crate::{Particle, PARTICLE_MASS};
+
+/// Chemical species of a particle. Density, viscosity and every pressure
+/// solver use the per-species mass and rest density (Solenthaler & Pajarola,
+/// Density Contrast SPH Interfaces, 2008), so immiscible fluids stratify under
+/// gravity.
+///
+/// Fields missing from a scene file are taken from water.
+#[derive(Component, Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Species {
+    pub name: String,
+    pub rest_density: f32,
+    pub mass: f32,
+    /// Relative to `ViscositySettings::coefficient`
+    pub viscosity: f32,
+    /// Coefficient of the `SurfaceTension` its particles spawn with
+    pub surface_tension: f32,
+    /// Thermal conductivity k and specific heat capacity c, in simulation
+    /// units where k / (ρ0 c) is the thermal diffusivity in px² / s
+    pub conductivity: f32,
+    pub heat_capacity: f32,
+    /// Transition temperatures in kelvin, and the latent heats of fusion and
+    /// vaporization per unit mass, absorbed at them
+    pub melting_point: f32,
+    pub boiling_point: f32,
+    pub fusion_heat: f32,
+    pub vaporization_heat: f32,
+    #[serde(with = "rgba")]
+    pub colour: Color,
+}
+
+// Colours are written as [r, g, b, a] so scene files do not depend on Bevy's
+// own serialization
+mod rgba {
+    use bevy::prelude::Color;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    pub fn serialize<S: Serializer>(colour: &Color, serializer: S) -> Result<S::Ok, S::Error> {
+        colour.as_rgba_f32().serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Color, D::Error> {
+        let [red, green, blue, alpha] = <[f32; 4]>::deserialize(deserializer)?;
+        Ok(Color::rgba(red, green, blue, alpha))
+    }
+}
+
+impl Species {
+    pub fn water() -> Self {
+        Species {
+            name: "water".into(),
+            rest_density: 3e-4,
+            mass: PARTICLE_MASS,
+            viscosity: 1.0,
+            surface_tension: 50.0,
+            conductivity: 0.3,
+            heat_capacity: 1.0,
+            // Latent heats in proportion to water's 334 and 2257 J/g
+            melting_point: 273.15,
+            boiling_point: 373.15,
+            fusion_heat: 80.0,
+            vaporization_heat: 540.0,
+            colour: Color::CYAN,
+        }
+    }
+
+    pub fn oil() -> Self {
+        // Mass in proportion to rest density so both phases settle at the
+        // same particle spacing
+        Species {
+            name: "oil".into(),
+            rest_density: 2.4e-4,
+            mass: 0.8 * PARTICLE_MASS,
+            viscosity: 5.0,
+            // Roughly oil's 30 mN/m against water's 72
+            surface_tension: 20.0,
+            conductivity: 0.06,
+            heat_capacity: 0.5,
+            melting_point: 250.0,
+            boiling_point: 570.0,
+            fusion_heat: 20.0,
+            vaporization_heat: 100.0,
+            colour: Color::rgb(0.9, 0.7, 0.1),
+        }
+    }
+}
+
+impl Default for Species {
+    fn default() -> Self {
+        Species::water()
+    }
+}
+
+/// Species available to the simulation, looked up by name.
+#[derive(Resource, Clone, Debug)]
+pub struct SpeciesTable {
+    pub species: Vec<Species>,
+}
+
+impl Default for SpeciesTable {
+    fn default() -> Self {
+        SpeciesTable {
+            species: vec![Species::water(), Species::oil()],
+        }
+    }
+}
+
+impl SpeciesTable {
+    pub fn get(&self, name: &str) -> Option<&Species> {
+        self.species.iter().find(|species| species.name == name)
+    }
+
+    /// Largest μ / ρ0 in the table, relative to `ViscositySettings::coefficient`
+    pub fn max_kinematic_viscosity(&self) -> f32 {
+        self.species
+            .iter()
+            .filter(|species| species.rest_density > 0.0)
+            .map(|species| species.viscosity / species.rest_density)
+            .fold(0.0, f32::max)
+    }
+
+    /// Largest k / (ρ0 c) in the table
+    pub fn max_thermal_diffusivity(&self) -> f32 {
+        self.species
+            .iter()
+            .filter(|species| species.rest_density > 0.0 && species.heat_capacity > 0.0)
+            .map(|species| species.conductivity / (species.rest_density * species.heat_capacity))
+            .fold(0.0, f32::max)
+    }
+}
+
+/// Species colour, darkened for solids and faded for gases.
+pub fn update_species_colour(mut query: Query<(&Species, &PhaseState, &mut Fill), With<Particle>>) {
+    for (species, state, mut fill) in query.iter_mut() {
+        let colour = state.phase.tint(species.colour);
+        if fill.color != colour {
+            fill.color = colour;
+        }
+    }
+}
@@ -0,0 +1,196 @@
+use bevy::prelude::*;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::integrator::Force;
+use crate::kernel::Kernels;
+use crate::phase::{Phase, PhaseState};
+use crate::spatial_hash::SpatialHash;
+use crate::species::Species;
+use crate::{Density, Particle};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SurfaceTensionModel {
+    /// Müller et al. colour field: F_i = -σ V_i ∇²c_i n_i / |n_i| at the surface
+    ColorField,
+    /// Akinci et al. cohesion spline plus surface-area minimizing curvature term
+    Akinci,
+}
+
+#[derive(Resource, Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct SurfaceTensionSettings {
+    pub model: SurfaceTensionModel,
+    /// Colour field only: particles with h|n| below this are treated as interior
+    pub surface_threshold: f32,
+}
+
+impl Default for SurfaceTensionSettings {
+    fn default() -> Self {
+        SurfaceTensionSettings {
+            model: SurfaceTensionModel::Akinci,
+            surface_threshold: 0.2,
+        }
+    }
+}
+
+/// Surface tension coefficient, taken from the particle's species. The
+/// simulation is 2D, so this is a force rather than a force per unit length.
+#[derive(Component, Clone, Copy, Debug)]
+pub struct SurfaceTension {
+    pub coefficient: f32,
+}
+
+impl Default for SurfaceTension {
+    fn default() -> Self {
+        SurfaceTension { coefficient: 50.0 }
+    }
+}
+
+// Akinci et al. cohesion spline, scaled to peak at 1 for r = h / 2 so that the
+// coefficient reads as a force per neighbour pair. Negative (repulsive) below
+// r = h / 4.
+fn cohesion(r: f32, h: f32) -> f32 {
+    if r >= h || r <= 0.0 {
+        return 0.0;
+    }
+
+    let scale = 64.0 / h.powi(6);
+    let shape = (h - r).powi(3) * r.powi(3);
+    if 2.0 * r > h {
+        scale * shape
+    } else {
+        scale * (2.0 * shape - h.powi(6) / 64.0)
+    }
+}
+
+pub fn surface_tension_force_system(hash: Res<SpatialHash>,
+                                    kernels: Res<Kernels>,
+                                    settings: Res<SurfaceTensionSettings>,
+                                    mut query: Query<(&mut Force, &Density, &Species, &SurfaceTension, &PhaseState), With<Particle>>) {
+    let mut densities = Vec::with_capacity(hash.len());
+    let mut masses = Vec::with_capacity(hash.len());
+    let mut rest_densities = Vec::with_capacity(hash.len());
+    let mut coefficients = Vec::with_capacity(hash.len());
+    for entity in hash.entities.iter() {
+        let (density, mass, rest_density, coefficient) = query
+            .get(*entity)
+            .map_or((0.0, 0.0, 0.0, 0.0), |(_, density, species, tension, state)| {
+                // Gases have no surface to hold together
+                let coefficient = if state.phase == Phase::Gas { 0.0 } else { tension.coefficient };
+                (density.value, species.mass, species.rest_density, coefficient)
+            });
+        densities.push(density);
+        masses.push(mass);
+        rest_densities.push(rest_density);
+        coefficients.push(coefficient);
+    }
+
+    let kernel = &kernels.density;
+    let h = kernel.radius();
+
+    // Colour field gradient n_i = sum_j V_j ∇W(r_ij)
+    let normals: Vec<Vec2> = (0..hash.len())
+        .into_par_iter()
+        .map(|i| {
+            let mut normal = Vec2::ZERO;
+            hash.for_each_neighbour(hash.positions[i], h, |j| {
+                if densities[j] > 0.0 {
+                    normal += masses[j] / densities[j]
+                        * kernel.gradient(hash.positions[i] - hash.positions[j]);
+                }
+            });
+            normal
+        })
+        .collect();
+
+    let forces: Vec<Vec2> = (0..hash.len())
+        .into_par_iter()
+        .map(|i| {
+            let mut force = Vec2::ZERO;
+            if densities[i] <= 0.0 {
+                return force;
+            }
+
+            match settings.model {
+                SurfaceTensionModel::ColorField => {
+                    if h * normals[i].length() < settings.surface_threshold {
+                        return force;
+                    }
+
+                    let mut laplacian = 0.0;
+                    hash.for_each_neighbour(hash.positions[i], h, |j| {
+                        if densities[j] > 0.0 {
+                            let distance = hash.positions[i].distance(hash.positions[j]);
+                            laplacian += masses[j] / densities[j] * kernel.laplacian(distance);
+                        }
+                    });
+
+                    force = -coefficients[i] * masses[i] / densities[i]
+                        * laplacian * normals[i].normalize_or_zero();
+                }
+                SurfaceTensionModel::Akinci => {
+                    hash.for_each_neighbour(hash.positions[i], h, |j| {
+                        if i == j || densities[j] <= 0.0 {
+                            return;
+                        }
+
+                        let offset = hash.positions[i] - hash.positions[j];
+                        let distance = offset.length();
+                        if distance <= f32::EPSILON {
+                            return;
+                        }
+
+                        let coefficient = 0.5 * (coefficients[i] + coefficients[j]);
+                        let correction = (rest_densities[i] + rest_densities[j]) / (densities[i] + densities[j]);
+
+                        let cohesion_force = -coefficient * masses[i] * masses[j]
+                            * cohesion(distance, h) * offset / distance;
+                        let curvature_force = -coefficient * masses[i] * h
+                            * (normals[i] - normals[j]);
+
+                        force += correction * (cohesion_force + curvature_force);
+                    });
+                }
+            }
+            force
+        })
+        .collect();
+
+    for (entity, value) in hash.entities.iter().zip(forces) {
+        if let Ok((mut force, _, _, _, _)) = query.get_mut(*entity) {
+            force.0 += value;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::ecs::system::RunSystemOnce;
+
+    use crate::kernel::KernelTypes;
+
+    // A lone particle has no colour field gradient, so with no threshold its
+    // normal must not be normalized into NaN
+    #[test]
+    fn colour_field_ignores_zero_normals() {
+        let mut world = World::new();
+        world.insert_resource(KernelTypes::default().build(75.0));
+        world.insert_resource(SurfaceTensionSettings { model: SurfaceTensionModel::ColorField, surface_threshold: 0.0 });
+
+        let species = Species::default();
+        let state = PhaseState::new(&species, 300.0);
+        let particle = world.spawn((Particle,
+                                    Force::default(),
+                                    Density { value: species.rest_density },
+                                    SurfaceTension { coefficient: 1.0 },
+                                    state,
+                                    species)).id();
+        let mut hash = SpatialHash::new(75.0);
+        hash.rebuild([(particle, Vec2::ZERO)].into_iter());
+        world.insert_resource(hash);
+
+        world.run_system_once(surface_tension_force_system);
+        assert_eq!(world.get::<Force>(particle).unwrap().0, Vec2::ZERO);
+    }
+}
@@ -0,0 +1,188 @@
+use bevy::ecs::schedule::ScheduleLabel;
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::integrator::{is_rapier_driven, Acceleration, IntegratorMode, LinearVelocity};
+use crate::kernel::Kernels;
+use crate::pressure::PressureSolver;
+use crate::solute::SoluteSettings;
+use crate::species::SpeciesTable;
+use crate::viscosity::ViscositySettings;
+use crate::Particle;
+
+/// One SPH step, through the `FluidSimSet` stages from neighbour search to
+/// integration. Run by `run_substeps` as many times as stability requires
+/// within a fixed update.
+#[derive(ScheduleLabel, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct SphStep;
+
+/// Adaptive timestep settings, plus the dt and substep count chosen for the
+/// last fixed update.
+#[derive(Resource, Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct TimeStep {
+    /// The dt of the current substep, which the last substep of a frame clips
+    /// to the time left in it
+    pub dt: f32,
+    /// The stable dt chosen for the last substep, before clipping
+    #[serde(default)]
+    pub chosen_dt: f32,
+    pub substeps: u32,
+    /// Frames cut short by `max_substeps`, which then advance the simulation
+    /// by less than the frame time
+    #[serde(default)]
+    pub truncated_frames: u32,
+    pub min_dt: f32,
+    pub max_dt: f32,
+    pub max_substeps: u32,
+    /// λ in dt ≤ λ h / v_max
+    pub cfl: f32,
+    /// λ in dt ≤ λ sqrt(h / a_max)
+    pub force_factor: f32,
+    /// λ in dt ≤ λ h² / ν
+    pub viscous_factor: f32,
+}
+
+impl Default for TimeStep {
+    fn default() -> Self {
+        TimeStep {
+            dt: 0.0,
+            chosen_dt: 0.0,
+            substeps: 0,
+            truncated_frames: 0,
+            min_dt: 1e-5,
+            max_dt: 1.0 / 60.0,
+            max_substeps: 64,
+            cfl: 0.4,
+            force_factor: 0.25,
+            viscous_factor: 0.125,
+        }
+    }
+}
+
+impl TimeStep {
+    /// Largest dt satisfying the CFL, force and viscous diffusion conditions.
+    pub fn stable_dt(&self, h: f32, max_speed: f32, max_acceleration: f32, kinematic_viscosity: f32) -> f32 {
+        let mut dt = self.max_dt;
+
+        if max_speed > 0.0 {
+            dt = dt.min(self.cfl * h / max_speed);
+        }
+        if max_acceleration > 0.0 {
+            dt = dt.min(self.force_factor * (h / max_acceleration).sqrt());
+        }
+        if kinematic_viscosity > 0.0 {
+            dt = dt.min(self.viscous_factor * h.powi(2) / kinematic_viscosity);
+        }
+
+        dt.max(self.min_dt)
+    }
+}
+
+pub fn run_substeps(world: &mut World) {
+    let frame_dt = world.resource::<Time>().delta_seconds();
+
+    // Rapier owns the timestep, so step the forces once alongside it
+    if is_rapier_driven(*world.resource::<IntegratorMode>(), *world.resource::<PressureSolver>()) {
+        let mut timestep = world.resource_mut::<TimeStep>();
+        timestep.dt = frame_dt;
+        timestep.chosen_dt = frame_dt;
+        timestep.substeps = 1;
+        world.run_schedule(SphStep);
+        return;
+    }
+
+    let h = world.resource::<Kernels>().pressure.radius();
+    let settings = *world.resource::<ViscositySettings>();
+    let species = world.resource::<SpeciesTable>();
+    // Heat and solutes diffuse under the same stability limit as momentum
+    let diffusivity = (settings.coefficient * species.max_kinematic_viscosity())
+        .max(species.max_thermal_diffusivity())
+        .max(world.resource::<SoluteSettings>().max_diffusivity());
+    // Artificial viscosity propagates at the speed of sound
+    let signal_speed = if settings.alpha > 0.0 || settings.beta > 0.0 {
+        settings.speed_of_sound
+    } else {
+        0.0
+    };
+
+    let mut particles = world.query_filtered::<(&LinearVelocity, &Acceleration), With<Particle>>();
+    let mut elapsed = 0.0;
+    let mut substeps = 0;
+
+    while elapsed < frame_dt && substeps < world.resource::<TimeStep>().max_substeps {
+        let (max_speed, max_acceleration) = particles
+            .iter(world)
+            .fold((0.0_f32, 0.0_f32), |(speed, acceleration), (v, a)| {
+                (speed.max(v.0.length()), acceleration.max(a.0.length()))
+            });
+
+        let mut timestep = world.resource_mut::<TimeStep>();
+        let chosen_dt = timestep.stable_dt(h, max_speed + signal_speed, max_acceleration, diffusivity);
+        let dt = chosen_dt.min(frame_dt - elapsed);
+        timestep.chosen_dt = chosen_dt;
+        timestep.dt = dt;
+
+        world.run_schedule(SphStep);
+
+        elapsed += dt;
+        substeps += 1;
+    }
+
+    let mut timestep = world.resource_mut::<TimeStep>();
+    timestep.substeps = substeps;
+    if elapsed < frame_dt {
+        timestep.truncated_frames += 1;
+        warn!("Stopped after {} substeps, {:.3} ms short of the {:.3} ms frame",
+              substeps, 1000.0 * (frame_dt - elapsed), 1000.0 * frame_dt);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    use crate::kernel::KernelTypes;
+
+    #[test]
+    fn stable_dt_takes_the_tightest_condition() {
+        let timestep = TimeStep::default();
+        let h = 75.0;
+
+        assert_eq!(timestep.stable_dt(h, 0.0, 0.0, 0.0), timestep.max_dt);
+        assert_eq!(timestep.stable_dt(h, 3000.0, 0.0, 0.0), 0.4 * 75.0 / 3000.0);
+        assert_eq!(timestep.stable_dt(h, 0.0, 3e6, 0.0), 0.25 * (75.0_f32 / 3e6).sqrt());
+        assert_eq!(timestep.stable_dt(h, 0.0, 0.0, 1e6), 0.125 * 75.0_f32.powi(2) / 1e6);
+        assert_eq!(timestep.stable_dt(h, 1e12, 0.0, 0.0), timestep.min_dt);
+    }
+
+    #[test]
+    fn substeps_keep_the_chosen_dt_and_count_truncated_frames() {
+        let mut world = World::new();
+        world.insert_resource(IntegratorMode::SymplecticEuler);
+        world.insert_resource(PressureSolver::Wcsph);
+        world.insert_resource(KernelTypes::default().build(75.0));
+        world.insert_resource(ViscositySettings { coefficient: 0.0, alpha: 0.0, beta: 0.0, ..default() });
+        world.insert_resource(SpeciesTable { species: Vec::new() });
+        world.insert_resource(SoluteSettings::default());
+        world.insert_resource(TimeStep { max_dt: 0.02, ..default() });
+        world.add_schedule(Schedule::new(SphStep));
+
+        let mut time = Time::<()>::default();
+        time.advance_by(Duration::from_millis(50));
+        world.insert_resource(time);
+
+        run_substeps(&mut world);
+        let timestep = *world.resource::<TimeStep>();
+        assert_eq!(timestep.substeps, 3);
+        assert_eq!(timestep.chosen_dt, 0.02);
+        assert!((timestep.dt - 0.01).abs() < 1e-6);
+        assert_eq!(timestep.truncated_frames, 0);
+
+        world.resource_mut::<TimeStep>().max_substeps = 2;
+        run_substeps(&mut world);
+        let timestep = *world.resource::<TimeStep>();
+        assert_eq!(timestep.substeps, 2);
+        assert_eq!(timestep.truncated_frames, 1);
+    }
+}
@@ -0,0 +1,115 @@
+use bevy::prelude::*;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::integrator::{Force, LinearVelocity};
+use crate::kernel::Kernels;
+use crate::spatial_hash::SpatialHash;
+use crate::species::Species;
+use crate::{Density, Particle};
+
+#[derive(Resource, Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct ViscositySettings {
+    /// μ in the Müller et al. laplacian term
+    pub coefficient: f32,
+    /// Monaghan artificial viscosity α, 0 disables it
+    pub alpha: f32,
+    /// Monaghan artificial viscosity β for high Mach number shocks
+    pub beta: f32,
+    pub speed_of_sound: f32,
+}
+
+impl ViscositySettings {
+    pub fn water() -> Self {
+        ViscositySettings {
+            coefficient: 20.0,
+            alpha: 0.0,
+            beta: 0.0,
+            speed_of_sound: 500.0,
+        }
+    }
+
+    pub fn honey() -> Self {
+        ViscositySettings {
+            coefficient: 2000.0,
+            ..ViscositySettings::water()
+        }
+    }
+}
+
+impl Default for ViscositySettings {
+    fn default() -> Self {
+        ViscositySettings::water()
+    }
+}
+
+// Laplacian term: F_i = m_i / ρ_i * sum_j μ_ij m_j (v_j - v_i) / ρ_j ∇²W(r_ij),
+// with μ_ij the mean of the two species' viscosities
+// Artificial term: F_i = -m_i * sum_j m_j Π_ij ∇W(r_ij), with
+// Π_ij = (-α c μ_ij + β μ_ij²) / ρ_ij for approaching pairs and
+// μ_ij = h v_ij·r_ij / (r_ij² + 0.01h²)
+pub fn viscosity_force_system(hash: Res<SpatialHash>,
+                              kernels: Res<Kernels>,
+                              settings: Res<ViscositySettings>,
+                              mut query: Query<(&mut Force, &LinearVelocity, &Density, &Species), With<Particle>>) {
+    let mut velocities = Vec::with_capacity(hash.len());
+    let mut densities = Vec::with_capacity(hash.len());
+    let mut masses = Vec::with_capacity(hash.len());
+    let mut viscosities = Vec::with_capacity(hash.len());
+    for entity in hash.entities.iter() {
+        let (velocity, density, mass, viscosity) = query
+            .get(*entity)
+            .map_or((Vec2::ZERO, 0.0, 0.0, 0.0), |(_, velocity, density, species)| {
+                (velocity.0, density.value, species.mass, species.viscosity)
+            });
+        velocities.push(velocity);
+        densities.push(density);
+        masses.push(mass);
+        viscosities.push(viscosity);
+    }
+
+    let h = kernels.viscosity.radius();
+    let artificial = settings.alpha > 0.0 || settings.beta > 0.0;
+
+    let forces: Vec<Vec2> = (0..hash.len())
+        .into_par_iter()
+        .map(|i| {
+            let mut force = Vec2::ZERO;
+            if densities[i] <= 0.0 {
+                return force;
+            }
+
+            hash.for_each_neighbour(hash.positions[i], h, |j| {
+                if i == j || densities[j] <= 0.0 {
+                    return;
+                }
+
+                let offset = hash.positions[i] - hash.positions[j];
+                let distance = offset.length();
+                let relative_velocity = velocities[i] - velocities[j];
+
+                let viscosity = settings.coefficient * 0.5 * (viscosities[i] + viscosities[j]);
+                force += masses[i] * viscosity / densities[i]
+                    * masses[j] * -relative_velocity / densities[j]
+                    * kernels.viscosity.laplacian(distance);
+
+                let approach = relative_velocity.dot(offset);
+                if artificial && approach < 0.0 {
+                    let mu = h * approach / (distance.powi(2) + 0.01 * h.powi(2));
+                    let mean_density = 0.5 * (densities[i] + densities[j]);
+                    let pi = (-settings.alpha * settings.speed_of_sound * mu
+                        + settings.beta * mu.powi(2)) / mean_density;
+
+                    force -= masses[i] * masses[j] * pi * kernels.pressure.gradient(offset);
+                }
+            });
+            force
+        })
+        .collect();
+
+    for (entity, value) in hash.entities.iter().zip(forces) {
+        if let Ok((mut force, _, _, _)) = query.get_mut(*entity) {
+            force.0 += value;
+        }
+    }
+}